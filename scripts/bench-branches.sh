@@ -0,0 +1,58 @@
+#!/usr/bin/env bash
+# Times `stats` on a synthetic clone with many remote branches, one commit each.
+#
+#   scripts/bench-branches.sh [-n BRANCHES] [-r RUNS] BINARY...
+#
+# Each binary is run RUNS times (5 by default) on the same repository, and the best
+# wall-clock time is printed. Pass the release builds of two commits to compare them.
+set -euo pipefail
+
+branches=2000
+runs=5
+while getopts "n:r:" opt; do
+    case $opt in
+        n) branches=$OPTARG ;;
+        r) runs=$OPTARG ;;
+        *) exit 2 ;;
+    esac
+done
+shift $((OPTIND - 1))
+if [ $# -eq 0 ]; then
+    echo "usage: $0 [-n BRANCHES] [-r RUNS] BINARY..." >&2
+    exit 2
+fi
+
+repo=$(mktemp -d)
+trap 'rm -rf "$repo"' EXIT
+git init --quiet "$repo"
+git -C "$repo" remote add origin https://example.invalid/bench.git
+
+# One root commit per branch, written straight into refs/remotes/origin with fast-import
+{
+    for i in $(seq 1 "$branches"); do
+        when=$((1600000000 + i * 3600))
+        message="commit $i"
+        echo "commit refs/remotes/origin/feature/$i"
+        echo "author Dev $((i % 50)) <dev$((i % 50))@example.com> $when +0000"
+        echo "committer Dev $((i % 50)) <dev$((i % 50))@example.com> $when +0000"
+        echo "data ${#message}"
+        echo "$message"
+        echo
+    done
+    echo "reset refs/remotes/origin/main"
+    echo "from refs/remotes/origin/feature/1"
+} | git -C "$repo" fast-import --quiet
+
+echo "$branches branches, best of $runs runs on $(nproc) cores"
+for binary in "$@"; do
+    best=
+    for _ in $(seq 1 "$runs"); do
+        start=$(date +%s%N)
+        "$binary" -l "$repo" stats >/dev/null
+        elapsed=$(( $(date +%s%N) - start ))
+        if [ -z "$best" ] || [ "$elapsed" -lt "$best" ]; then
+            best=$elapsed
+        fi
+    done
+    printf '%s: %d.%03ds\n' "$binary" $((best / 1000000000)) $((best / 1000000 % 1000))
+done
@@ -0,0 +1,194 @@
+//! Credentials for talking to remotes over SSH and HTTPS.
+//!
+//! SSH tries the agent then the default keys. HTTPS tries a token from `GIT_HARVEST_TOKEN`,
+//! or from `GITHUB_TOKEN` for GitHub only, meant for CI runners, then the git credential
+//! helper.
+
+use git2::{Config, Cred, CredentialType, ErrorClass, ErrorCode, RemoteCallbacks};
+use log::debug;
+use std::path::PathBuf;
+
+/// Private keys tried after the SSH agent, in order
+const DEFAULT_SSH_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Environment variable holding a token for HTTPS remotes on any host
+const TOKEN_VARIABLE: &str = "GIT_HARVEST_TOKEN";
+
+/// Environment variable holding a GitHub token, only ever sent to GitHub
+const GITHUB_TOKEN_VARIABLE: &str = "GITHUB_TOKEN";
+
+/// User name sent with the token when the URL has none; GitHub expects this one and GitLab
+/// takes any name with a token
+const TOKEN_USERNAME: &str = "x-access-token";
+
+/// The host of an http(s) URL, without the user and the port
+fn url_host(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?;
+    Some(host.to_lowercase()).filter(|host| !host.is_empty())
+}
+
+/// Whether the URL is on GitHub, or on the GitHub Enterprise server Actions runs against
+fn is_github(url: &str) -> bool {
+    let Some(host) = url_host(url) else {
+        return false;
+    };
+    let server = std::env::var("GITHUB_SERVER_URL")
+        .ok()
+        .and_then(|server| url_host(&server));
+    host == "github.com" || server.is_some_and(|server| server == host)
+}
+
+/// A token from the environment for the URL, for CI runners without a credential helper.
+/// A GitHub token is kept from other hosts, they have no business seeing it.
+fn env_token(url: &str) -> Option<String> {
+    let mut names = vec![TOKEN_VARIABLE];
+    if is_github(url) {
+        names.push(GITHUB_TOKEN_VARIABLE);
+    }
+    names.into_iter().find_map(|name| {
+        let token = std::env::var(name)
+            .ok()
+            .filter(|token| !token.trim().is_empty())?;
+        debug!("found a token in {}", name);
+        Some(token.trim().to_string())
+    })
+}
+
+fn default_ssh_keys() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME") else {
+        return Vec::new();
+    };
+    let ssh_dir = PathBuf::from(home).join(".ssh");
+    DEFAULT_SSH_KEYS
+        .iter()
+        .map(|name| ssh_dir.join(name))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// libgit2 asks for credentials again after every rejected attempt, this hands out
+/// each method once: the SSH agent, the default SSH keys, a token from the environment for
+/// HTTPS, then the git credential helper
+struct CredentialChain {
+    username_tried: bool,
+    agent_tried: bool,
+    ssh_keys: Vec<PathBuf>,
+    token_tried: bool,
+    helper_tried: bool,
+    default_tried: bool,
+}
+
+impl CredentialChain {
+    fn new() -> CredentialChain {
+        CredentialChain {
+            username_tried: false,
+            agent_tried: false,
+            ssh_keys: default_ssh_keys(),
+            token_tried: false,
+            helper_tried: false,
+            default_tried: false,
+        }
+    }
+
+    fn next(
+        &mut self,
+        config: &Config,
+        url: &str,
+        username: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let user = username.unwrap_or("git");
+        if allowed.contains(CredentialType::USERNAME) && !self.username_tried {
+            self.username_tried = true;
+            return Cred::username(user);
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !self.agent_tried {
+                self.agent_tried = true;
+                debug!("trying the SSH agent for {}", url);
+                if let Ok(cred) = Cred::ssh_key_from_agent(user) {
+                    return Ok(cred);
+                }
+            }
+            while !self.ssh_keys.is_empty() {
+                let key = self.ssh_keys.remove(0);
+                debug!("trying SSH key {} for {}", key.display(), url);
+                if let Ok(cred) = Cred::ssh_key(user, None, &key, None) {
+                    return Ok(cred);
+                }
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && url.starts_with("https://")
+            && !self.token_tried
+        {
+            // Tried once, so a rejected token falls through to the helper
+            self.token_tried = true;
+            if let Some(token) = env_token(url) {
+                debug!("trying the token from the environment for {}", url);
+                return Cred::userpass_plaintext(username.unwrap_or(TOKEN_USERNAME), &token);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && !self.helper_tried {
+            self.helper_tried = true;
+            debug!("trying the git credential helper for {}", url);
+            if let Ok(cred) = Cred::credential_helper(config, url, username) {
+                return Ok(cred);
+            }
+        }
+        if allowed.contains(CredentialType::DEFAULT) && !self.default_tried {
+            self.default_tried = true;
+            return Cred::default();
+        }
+        Err(git2::Error::new(
+            ErrorCode::Auth,
+            ErrorClass::Callback,
+            "no credentials accepted",
+        ))
+    }
+}
+
+/// Callbacks authenticating against the remote for fetches and pushes
+pub fn remote_callbacks(config: &Config) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut chain = CredentialChain::new();
+    callbacks.credentials(move |url, username, allowed| {
+        debug!("authenticating to {} ({:?})", url, allowed);
+        chain.next(config, url, username, allowed)
+    });
+    callbacks
+}
+
+/// Describes a failed remote operation, telling authentication failures apart
+pub fn describe_error(err: &git2::Error) -> String {
+    if err.code() == ErrorCode::Auth {
+        "authentication failed".to_string()
+    } else {
+        err.message().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_host_drops_user_and_port() {
+        assert_eq!(
+            url_host("https://x-access-token@GitHub.com:443/o/r.git").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(url_host("git@github.com:o/r.git"), None);
+    }
+
+    #[test]
+    fn github_token_is_only_for_github() {
+        assert!(is_github("https://github.com/o/r.git"));
+        assert!(!is_github("https://gitlab.com/o/r.git"));
+        assert!(!is_github("https://github.com.evil.example/o/r.git"));
+        assert!(!is_github("https://git.internal/github.com/r.git"));
+    }
+}
@@ -0,0 +1,513 @@
+//! The `browse` terminal UI, built with the `tui` feature.
+//!
+//! Branches are shown in a table that narrows down as a filter is typed. Branches are picked
+//! with space and deleted together after a confirmation, and a second one when some of them
+//! are not merged, unless `--force` is given. Deleting is left to the caller, so it follows
+//! the same path as cleanup.
+
+use crate::BranchInfo;
+use log::LevelFilter;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeSet;
+use std::io;
+
+/// A branch as the table shows it
+pub struct Entry {
+    pub branch: BranchInfo,
+    pub age: i64,
+    pub merged: bool,
+    pub protected: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Column {
+    Name,
+    Author,
+    Age,
+    Merged,
+}
+
+impl Column {
+    fn next(self) -> Column {
+        match self {
+            Column::Name => Column::Author,
+            Column::Author => Column::Age,
+            Column::Age => Column::Merged,
+            Column::Merged => Column::Name,
+        }
+    }
+}
+
+struct App {
+    entries: Vec<Entry>,
+    // Deleted entries stay in place, so indices into entries remain valid
+    gone: Vec<bool>,
+    visible: Vec<usize>,
+    picked: BTreeSet<usize>,
+    filter: String,
+    sort: Column,
+    descending: bool,
+    table: TableState,
+    confirming: Option<Confirming>,
+    // Unmerged picks go after the first confirmation
+    force: bool,
+    status: String,
+    deleted: usize,
+}
+
+/// The confirmation shown over the table
+#[derive(Clone, Copy, PartialEq)]
+enum Confirming {
+    Delete,
+    /// Asked after Delete when some of the picked branches are not merged
+    Unmerged,
+}
+
+impl App {
+    fn new(entries: Vec<Entry>, force: bool) -> App {
+        let mut app = App {
+            gone: vec![false; entries.len()],
+            entries,
+            visible: Vec::new(),
+            picked: BTreeSet::new(),
+            filter: String::new(),
+            sort: Column::Age,
+            descending: true,
+            table: TableState::default(),
+            confirming: None,
+            force,
+            status: String::new(),
+            deleted: 0,
+        };
+        app.refresh();
+        app
+    }
+
+    /// Recomputes the rows shown, after the filter, the sort or the branches changed
+    fn refresh(&mut self) {
+        let filter = self.filter.to_lowercase();
+        let entries = &self.entries;
+        let mut visible: Vec<usize> = (0..entries.len())
+            .filter(|&index| !self.gone[index])
+            .filter(|&index| {
+                let branch = &entries[index].branch;
+                filter.is_empty()
+                    || branch.name.to_lowercase().contains(&filter)
+                    || branch.author_email.to_lowercase().contains(&filter)
+            })
+            .collect();
+        visible.sort_by(|&a, &b| {
+            let (a, b) = (&entries[a], &entries[b]);
+            let order = match self.sort {
+                Column::Name => a.branch.name.cmp(&b.branch.name),
+                Column::Author => a
+                    .branch
+                    .author_email
+                    .to_lowercase()
+                    .cmp(&b.branch.author_email.to_lowercase()),
+                Column::Age => a.age.cmp(&b.age),
+                Column::Merged => a.merged.cmp(&b.merged),
+            };
+            let order = order.then_with(|| a.branch.name.cmp(&b.branch.name));
+            if self.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        self.visible = visible;
+        let selected = match self.table.selected() {
+            _ if self.visible.is_empty() => None,
+            Some(row) => Some(row.min(self.visible.len() - 1)),
+            None => Some(0),
+        };
+        self.table.select(selected);
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.table
+            .selected()
+            .and_then(|row| self.visible.get(row).copied())
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let row = self.table.selected().unwrap_or(0) as isize + offset;
+        let row = row.clamp(0, self.visible.len() as isize - 1);
+        self.table.select(Some(row as usize));
+    }
+
+    fn toggle_pick(&mut self) {
+        let Some(index) = self.current() else {
+            return;
+        };
+        if self.entries[index].protected {
+            self.status = format!("{} is protected", self.entries[index].branch.name);
+        } else if !self.picked.remove(&index) {
+            self.picked.insert(index);
+        }
+        self.move_by(1);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let arrow = if self.descending { "▼" } else { "▲" };
+        let title = |column: Column, label: &str| match column == self.sort {
+            true => format!("{} {}", label, arrow),
+            false => label.to_string(),
+        };
+        let heading = Row::new([
+            Cell::from(""),
+            Cell::from(title(Column::Name, "BRANCH")),
+            Cell::from(title(Column::Author, "AUTHOR")),
+            Cell::from(title(Column::Age, "AGE (DAYS)")),
+            Cell::from(title(Column::Merged, "MERGED")),
+        ])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.visible.iter().map(|&index| {
+            let entry = &self.entries[index];
+            let mark = if entry.protected {
+                "[-]"
+            } else if self.picked.contains(&index) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            let merged = if entry.merged { "yes" } else { "no" };
+            let style = match (self.picked.contains(&index), entry.protected) {
+                (true, _) => Style::default().fg(Color::Red),
+                (false, true) => Style::default().fg(Color::DarkGray),
+                (false, false) => Style::default(),
+            };
+            Row::new([
+                Cell::from(mark),
+                Cell::from(entry.branch.display_name()),
+                Cell::from(entry.branch.author_email.clone())
+                    .style(Style::default().fg(Color::Cyan)),
+                Cell::from(entry.age.to_string()).style(Style::default().fg(Color::Yellow)),
+                Cell::from(merged),
+            ])
+            .style(style)
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Fill(3),
+                Constraint::Fill(2),
+                Constraint::Length(12),
+                Constraint::Length(8),
+            ],
+        )
+        .header(heading)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, body, &mut self.table);
+
+        let filter = format!(
+            "filter: {}_   {} of {} branches, {} picked",
+            self.filter,
+            self.visible.len(),
+            self.gone.iter().filter(|gone| !**gone).count(),
+            self.picked.len()
+        );
+        frame.render_widget(Paragraph::new(filter), header);
+        let help = if self.status.is_empty() {
+            "type to filter  ↑↓ move  space pick  tab sort  shift-tab reverse  ctrl-d delete  \
+             esc quit"
+                .to_string()
+        } else {
+            self.status.clone()
+        };
+        frame.render_widget(
+            Paragraph::new(help).style(Style::default().fg(Color::DarkGray)),
+            footer,
+        );
+
+        if let Some(confirming) = self.confirming {
+            let unmerged = self.unmerged_picks();
+            let warning = Style::default().fg(Color::Red);
+            let mut lines = Vec::new();
+            if confirming == Confirming::Delete {
+                lines.push(Line::from(format!(
+                    "Delete {} branches?",
+                    self.picked.len()
+                )));
+                if unmerged > 0 {
+                    lines.push(
+                        Line::from(format!("{} of them are not merged", unmerged)).style(warning),
+                    );
+                }
+                lines.push(Line::from("y to delete, any other key to go back"));
+            } else {
+                lines.push(
+                    Line::from(format!(
+                        "{} of the branches are not merged, their commits may be lost",
+                        unmerged
+                    ))
+                    .style(warning),
+                );
+                lines.push(Line::from("Y to delete them too, any other key to go back"));
+            }
+            let area = centered(frame.area(), 64, lines.len() as u16 + 2);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(lines).block(Block::default().borders(Borders::ALL)),
+                area,
+            );
+        }
+    }
+
+    /// Handles a key press, false once the user asked to quit
+    fn handle(
+        &mut self,
+        key: KeyEvent,
+        delete: &mut dyn FnMut(&BranchInfo) -> Result<(), String>,
+    ) -> bool {
+        if let Some(confirming) = self.confirming.take() {
+            match (confirming, key.code) {
+                (Confirming::Delete, KeyCode::Char('y'))
+                    if self.unmerged_picks() > 0 && !self.force =>
+                {
+                    self.confirming = Some(Confirming::Unmerged);
+                }
+                (Confirming::Delete, KeyCode::Char('y'))
+                | (Confirming::Unmerged, KeyCode::Char('Y')) => self.delete_picked(delete),
+                _ => {}
+            }
+            return true;
+        }
+        self.status.clear();
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => return false,
+            KeyCode::Esc if self.filter.is_empty() => return false,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.refresh();
+            }
+            KeyCode::Char('d') if control => {
+                if self.picked.is_empty() {
+                    self.status = "pick branches with space first".to_string();
+                } else {
+                    self.confirming = Some(Confirming::Delete);
+                }
+            }
+            KeyCode::Char(' ') => self.toggle_pick(),
+            KeyCode::Char(c) if !control => {
+                self.filter.push(c);
+                self.refresh();
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.refresh();
+            }
+            KeyCode::Tab => {
+                self.sort = self.sort.next();
+                self.refresh();
+            }
+            KeyCode::BackTab => {
+                self.descending = !self.descending;
+                self.refresh();
+            }
+            KeyCode::Up => self.move_by(-1),
+            KeyCode::Down => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-20),
+            KeyCode::PageDown => self.move_by(20),
+            _ => {}
+        }
+        true
+    }
+
+    fn unmerged_picks(&self) -> usize {
+        self.picked
+            .iter()
+            .filter(|&&index| !self.entries[index].merged)
+            .count()
+    }
+
+    fn delete_picked(&mut self, delete: &mut dyn FnMut(&BranchInfo) -> Result<(), String>) {
+        let mut failures = Vec::new();
+        let mut deleted = 0;
+        for index in std::mem::take(&mut self.picked) {
+            match delete(&self.entries[index].branch) {
+                Ok(()) => {
+                    self.gone[index] = true;
+                    deleted += 1;
+                }
+                Err(err) => {
+                    failures.push(err);
+                    // Left picked, so it can be tried again
+                    self.picked.insert(index);
+                }
+            }
+        }
+        self.deleted += deleted;
+        self.status = match failures.first() {
+            None => format!("deleted {} branches", deleted),
+            Some(first) => format!(
+                "deleted {} branches, {} failed: {}",
+                deleted,
+                failures.len(),
+                first
+            ),
+        };
+        self.refresh();
+    }
+}
+
+/// A width by height area in the middle of the screen
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    delete: &mut dyn FnMut(&BranchInfo) -> Result<(), String>,
+) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            // Windows reports releases too
+            if key.kind == KeyEventKind::Press && !app.handle(key, delete) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Runs the UI until it is quit, deleting branches through `delete`. Returns how many were
+/// deleted.
+pub fn run(
+    entries: Vec<Entry>,
+    force: bool,
+    mut delete: impl FnMut(&BranchInfo) -> Result<(), String>,
+) -> io::Result<usize> {
+    let mut app = App::new(entries, force);
+    // Log lines written to stderr would draw over the screen
+    let level = log::max_level();
+    log::set_max_level(LevelFilter::Off);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app, &mut delete);
+    ratatui::restore();
+    log::set_max_level(level);
+    result?;
+    Ok(app.deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{BranchType, Oid, Time};
+
+    fn entry(name: &str, merged: bool, protected: bool) -> Entry {
+        Entry {
+            branch: BranchInfo {
+                name: name.to_string(),
+                kind: BranchType::Remote,
+                author_name: "Alice".to_string(),
+                author_email: "alice@corp.com".to_string(),
+                committer_name: "Alice".to_string(),
+                committer_email: "alice@corp.com".to_string(),
+                time: Time::new(0, 0),
+                tip: Oid::zero(),
+            },
+            age: 10,
+            merged,
+            protected,
+        }
+    }
+
+    /// Picks every branch, asks to delete them and answers with the keys, returning the
+    /// names of the branches deleted
+    fn pick_all_and_delete(app: &mut App, answers: &[KeyCode]) -> Vec<String> {
+        let mut deleted = Vec::new();
+        let mut delete = |branch: &BranchInfo| {
+            deleted.push(branch.name.clone());
+            Ok(())
+        };
+        let press = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        for _ in 0..app.visible.len() {
+            app.handle(press(KeyCode::Char(' ')), &mut delete);
+        }
+        app.handle(
+            KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL),
+            &mut delete,
+        );
+        for &code in answers {
+            app.handle(press(code), &mut delete);
+        }
+        deleted.sort();
+        deleted
+    }
+
+    #[test]
+    fn merged_branches_go_after_one_confirmation() {
+        let mut app = App::new(
+            vec![entry("a", true, false), entry("b", true, false)],
+            false,
+        );
+        assert_eq!(
+            pick_all_and_delete(&mut app, &[KeyCode::Char('y')]),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn unmerged_branches_need_a_second_confirmation() {
+        let entries = || vec![entry("merged", true, false), entry("wip", false, false)];
+        let mut app = App::new(entries(), false);
+        assert!(pick_all_and_delete(&mut app, &[KeyCode::Char('y')]).is_empty());
+        assert!(app.confirming == Some(Confirming::Unmerged));
+
+        let mut app = App::new(entries(), false);
+        let answers = [KeyCode::Char('y'), KeyCode::Char('y')];
+        assert!(pick_all_and_delete(&mut app, &answers).is_empty());
+        assert!(app.confirming.is_none());
+
+        let mut app = App::new(entries(), false);
+        let answers = [KeyCode::Char('y'), KeyCode::Char('Y')];
+        assert_eq!(pick_all_and_delete(&mut app, &answers), ["merged", "wip"]);
+    }
+
+    #[test]
+    fn force_skips_the_second_confirmation() {
+        let mut app = App::new(vec![entry("wip", false, false)], true);
+        assert_eq!(
+            pick_all_and_delete(&mut app, &[KeyCode::Char('y')]),
+            ["wip"]
+        );
+    }
+
+    #[test]
+    fn protected_branches_can_not_be_picked() {
+        let mut app = App::new(
+            vec![entry("kept", true, true), entry("old", true, false)],
+            false,
+        );
+        assert_eq!(
+            pick_all_and_delete(&mut app, &[KeyCode::Char('y')]),
+            ["old"]
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! On-disk cache of what `--cache` remembers about branch tips.
+//!
+//! Loading the tip commit of every branch is what makes large repositories slow, the cache
+//! keeps the identities and date of each branch's tip so the next run only loads the tips
+//! that moved. Entries are kept per repository and per `--by` identity, keyed by branch,
+//! and a branch whose tip changed is looked up again. Changes to `.mailmap` are not
+//! noticed, delete the file after editing it.
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Bumped whenever the layout changes, older files are then ignored
+const VERSION: u32 = 2;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub tip: String,
+    pub name: String,
+    pub email: String,
+    pub committer_name: String,
+    pub committer_email: String,
+    pub time: i64,
+    pub offset: i32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Cache {
+    version: u32,
+    repositories: HashMap<String, HashMap<String, Entry>>,
+}
+
+impl Cache {
+    /// Reads the cache, starting afresh when the file is missing, unreadable or outdated.
+    pub fn load(path: &Path) -> Cache {
+        let cache = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Cache>(&content).ok())
+            .filter(|cache| cache.version == VERSION);
+        match cache {
+            Some(cache) => cache,
+            None => {
+                debug!("starting a new cache in '{}'", path.display());
+                Cache {
+                    version: VERSION,
+                    repositories: HashMap::new(),
+                }
+            }
+        }
+    }
+
+    /// The entry of a branch, when it was cached at the same tip.
+    pub fn get(&self, repository: &str, branch: &str, tip: &str) -> Option<&Entry> {
+        self.repositories
+            .get(repository)?
+            .get(branch)
+            .filter(|entry| entry.tip == tip)
+    }
+
+    /// Replaces everything known about a repository, dropping the branches that are gone.
+    pub fn replace(&mut self, repository: String, entries: HashMap<String, Entry>) {
+        self.repositories.insert(repository, entries);
+    }
+
+    /// Writes the cache through a temporary file, so a reader never sees half of it.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, content)
+            .and_then(|()| std::fs::rename(&temporary, path))
+            .map_err(|e| format!("unable to write cache '{}': {}", path.display(), e))
+    }
+}
@@ -0,0 +1,397 @@
+//! Team defaults read from a `.git-harvest.toml` file.
+//!
+//! The file is looked up in the repository root and in `$XDG_CONFIG_HOME/git-harvest/`
+//! (`~/.config/git-harvest/` when unset). Command line flags take precedence over the
+//! repository file, which takes precedence over the user file:
+//!
+//! ```toml
+//! location = "/home/me/src/service"   # only honored in the user file
+//! remote = "upstream"
+//! protect = ["integration/*"]
+//! stale_days = 60
+//! bulk_threshold = 20
+//! naming = ["feature/*", "bugfix/*"]
+//! ```
+//!
+//! `git-harvest config get` and `config set` read and edit these files.
+
+use crate::error::HarvestError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = ".git-harvest.toml";
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Repository to operate on when --location is not given
+    pub location: Option<String>,
+    /// Remote to operate on when --remote is not given
+    pub remote: Option<String>,
+    /// Globs cleanup never deletes, on top of the built-in protected branches
+    pub protect: Vec<String>,
+    /// Default threshold of the stale subcommand
+    pub stale_days: Option<u64>,
+    /// Number of branches above which cleanup --yes asks to confirm the count
+    pub bulk_threshold: Option<usize>,
+    /// Globs the names of remote branches are expected to match, checked by orphans
+    pub naming: Vec<String>,
+}
+
+impl Settings {
+    /// Fills in every key this file leaves unset from a lower precedence file.
+    pub fn or(self, fallback: Settings) -> Settings {
+        let mut protect = self.protect;
+        protect.extend(fallback.protect);
+        let mut naming = self.naming;
+        naming.extend(fallback.naming);
+        Settings {
+            location: self.location.or(fallback.location),
+            remote: self.remote.or(fallback.remote),
+            protect,
+            stale_days: self.stale_days.or(fallback.stale_days),
+            bulk_threshold: self.bulk_threshold.or(fallback.bulk_threshold),
+            naming,
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Settings, HarvestError> {
+    if !path.is_file() {
+        return Ok(Settings::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| HarvestError::Config(format!("unable to read '{}': {}", path.display(), e)))?;
+    let settings = toml::from_str(&content).map_err(|e| {
+        HarvestError::Config(format!("invalid config file '{}': {}", path.display(), e))
+    })?;
+    Ok(settings)
+}
+
+/// Location of the per-user configuration file.
+pub fn user_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("git-harvest").join(FILE_NAME))
+}
+
+pub fn load_user() -> Result<Settings, HarvestError> {
+    match user_path() {
+        Some(path) => load(&path),
+        None => Ok(Settings::default()),
+    }
+}
+
+pub fn load_repo(repo_root: &Path) -> Result<Settings, HarvestError> {
+    let mut settings = load(&repo_root.join(FILE_NAME))?;
+    // The repository file is found through the location, it can't move it
+    settings.location = None;
+    Ok(settings)
+}
+
+/// Keys `config get` and `config set` know about
+pub const KEYS: [&str; 6] = [
+    "location",
+    "remote",
+    "protect",
+    "stale_days",
+    "bulk_threshold",
+    "naming",
+];
+
+fn check_key(key: &str) -> Result<(), String> {
+    if KEYS.contains(&key) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown key '{}', expected one of {}",
+            key,
+            KEYS.join(", ")
+        ))
+    }
+}
+
+/// The values of a key, one per glob for protect and naming, None when unset.
+pub fn get(settings: &Settings, key: &str) -> Result<Option<Vec<String>>, String> {
+    check_key(key)?;
+    let values = match key {
+        "location" => settings.location.clone().map(|value| vec![value]),
+        "remote" => settings.remote.clone().map(|value| vec![value]),
+        "protect" => Some(settings.protect.clone()).filter(|globs| !globs.is_empty()),
+        "naming" => Some(settings.naming.clone()).filter(|globs| !globs.is_empty()),
+        "stale_days" => settings.stale_days.map(|days| vec![days.to_string()]),
+        _ => settings.bulk_threshold.map(|count| vec![count.to_string()]),
+    };
+    Ok(values)
+}
+
+/// The TOML for the new value of a key, after checking it is one the key accepts.
+fn render(key: &str, values: &[String]) -> Result<String, String> {
+    let quote = |value: &str| toml::Value::String(value.to_string()).to_string();
+    let single = || match values {
+        [value] => Ok(value.as_str()),
+        _ => Err(format!("{} takes a single value", key)),
+    };
+    match key {
+        "location" | "remote" => {
+            let value = single()?;
+            if value.is_empty() {
+                return Err(format!("{} can't be empty", key));
+            }
+            Ok(quote(value))
+        }
+        "protect" | "naming" => {
+            for glob in values {
+                globset::Glob::new(glob)
+                    .map_err(|e| format!("invalid {} glob '{}': {}", key, glob, e))?;
+            }
+            let globs: Vec<String> = values.iter().map(|glob| quote(glob)).collect();
+            Ok(format!("[{}]", globs.join(", ")))
+        }
+        "stale_days" => {
+            let value = single()?;
+            let days: u64 = value
+                .parse()
+                .map_err(|_| format!("stale_days must be a number of days, not '{}'", value))?;
+            Ok(days.to_string())
+        }
+        _ => {
+            let value = single()?;
+            let count: usize = value.parse().map_err(|_| {
+                format!(
+                    "bulk_threshold must be a number of branches, not '{}'",
+                    value
+                )
+            })?;
+            Ok(count.to_string())
+        }
+    }
+}
+
+/// Where the value assigned on a line ends: where its trailing comment starts if any, and the
+/// end of the last line it spans, for arrays written over several lines.
+fn value_extent(text: &str) -> (Option<usize>, usize) {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    // Set while skipping a comment inside an array
+    let mut skip_to = 0;
+    for (i, c) in text.char_indices() {
+        if i < skip_to {
+            continue;
+        }
+        if let Some(open) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && open == '"' {
+                escaped = true;
+            } else if c == open {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            '#' => {
+                let end = text[i..].find('\n').map_or(text.len(), |n| i + n);
+                if depth == 0 {
+                    return (Some(i), end);
+                }
+                skip_to = end;
+            }
+            '\n' if depth == 0 => return (None, i),
+            _ => {}
+        }
+    }
+    (None, text.len())
+}
+
+/// The key assigned on a line of the top-level table, if any
+fn assigned_key(line: &str) -> Option<&str> {
+    let (key, _) = line.split_once('=')?;
+    let key = key.trim().trim_matches('"');
+    Some(key).filter(|key| {
+        !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// Sets a key in a config file, creating the file when needed.
+///
+/// The file is edited in place rather than written anew, so comments, the order of the keys
+/// and the layout of the other lines are kept. A comment at the end of the replaced line is
+/// kept too.
+pub fn set(path: &Path, key: &str, values: &[String]) -> Result<(), HarvestError> {
+    edit(path, key, values).map_err(HarvestError::Config)
+}
+
+fn edit(path: &Path, key: &str, values: &[String]) -> Result<(), String> {
+    check_key(key)?;
+    let assignment = format!("{} = {}", key, render(key, values)?);
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("unable to read '{}': {}", path.display(), e)),
+    };
+    let mut updated = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            // Only the top-level table is read, stop at the first table header
+            let mut text = content[..start].to_string();
+            ensure_newline(&mut text);
+            text.push_str(&assignment);
+            text.push('\n');
+            text.push_str(&content[start..]);
+            updated = Some(text);
+            break;
+        }
+        if assigned_key(trimmed) != Some(key) {
+            continue;
+        }
+        let value_start = start + line.find('=').unwrap_or_default() + 1;
+        let (comment, end) = value_extent(&content[value_start..]);
+        let mut text = content[..start].to_string();
+        text.push_str(&line[..line.len() - trimmed.len()]);
+        text.push_str(&assignment);
+        if let Some(comment) = comment {
+            text.push_str("  ");
+            text.push_str(&content[value_start + comment..value_start + end]);
+        }
+        text.push_str(&content[value_start + end..]);
+        updated = Some(text);
+        break;
+    }
+    let updated = updated.unwrap_or_else(|| {
+        let mut text = content.clone();
+        ensure_newline(&mut text);
+        text.push_str(&assignment);
+        text.push('\n');
+        text
+    });
+    // Never leave behind a file the next run would refuse
+    toml::from_str::<Settings>(&updated)
+        .map_err(|e| format!("refusing to write '{}': {}", path.display(), e))?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("unable to create '{}': {}", dir.display(), e))?;
+    }
+    std::fs::write(path, updated)
+        .map_err(|e| format!("unable to write '{}': {}", path.display(), e))?;
+    Ok(())
+}
+
+fn ensure_newline(text: &mut String) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets the key in a file holding `content` and returns what the file holds afterwards
+    fn set_in(content: &str, key: &str, values: &[&str]) -> Result<String, HarvestError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        std::fs::write(&path, content).unwrap();
+        let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        set(&path, key, &values)?;
+        Ok(std::fs::read_to_string(&path).unwrap())
+    }
+
+    #[test]
+    fn an_existing_key_is_replaced_in_place() {
+        let content = "# team defaults\nremote = \"origin\"\nstale_days = 90\n";
+        assert_eq!(
+            set_in(content, "stale_days", &["60"]).unwrap(),
+            "# team defaults\nremote = \"origin\"\nstale_days = 60\n"
+        );
+    }
+
+    #[test]
+    fn a_new_key_is_appended() {
+        assert_eq!(
+            set_in("remote = \"origin\"", "stale_days", &["60"]).unwrap(),
+            "remote = \"origin\"\nstale_days = 60\n"
+        );
+        assert_eq!(set_in("", "remote", &["up"]).unwrap(), "remote = \"up\"\n");
+    }
+
+    #[test]
+    fn trailing_comments_are_kept() {
+        let content = "stale_days = 90 # agreed in the retro\nremote = \"origin\"\n";
+        assert_eq!(
+            set_in(content, "stale_days", &["60"]).unwrap(),
+            "stale_days = 60  # agreed in the retro\nremote = \"origin\"\n"
+        );
+    }
+
+    #[test]
+    fn an_array_over_several_lines_is_replaced_whole() {
+        let content =
+            "protect = [\n  \"release/*\", # cut monthly\n  \"hotfix/*\",\n]\nstale_days = 90\n";
+        assert_eq!(
+            set_in(content, "protect", &["integration/*"]).unwrap(),
+            "protect = [\"integration/*\"]\nstale_days = 90\n"
+        );
+    }
+
+    #[test]
+    fn a_hash_inside_a_string_is_not_a_comment() {
+        let content = "remote = \"team#1\" # the team's fork\n";
+        // The comment starts after the closing quote and runs to the end of the line
+        assert_eq!(value_extent(&content[8..]), (Some(10), 27));
+        assert_eq!(
+            set_in(content, "remote", &["team#2"]).unwrap(),
+            "remote = \"team#2\"  # the team's fork\n"
+        );
+    }
+
+    #[test]
+    fn a_file_the_settings_would_refuse_is_not_written() {
+        let content = "remote = \"origin\"\n[extra]\nstale_days = 1\n";
+        let err = set_in(content, "stale_days", &["60"]).unwrap_err();
+        assert!(err.to_string().contains("refusing to write"), "{}", err);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected_and_the_file_left_alone() {
+        let content = "stale_days = 90\n";
+        for (key, values) in [
+            ("stale_days", &["soon"][..]),
+            ("bulk_threshold", &["-1"]),
+            ("remote", &[""]),
+            ("remote", &["a", "b"]),
+            ("protect", &["release/["]),
+            ("unknown", &["1"]),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join(FILE_NAME);
+            std::fs::write(&path, content).unwrap();
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            let err = set(&path, key, &values).unwrap_err();
+            assert_eq!(err.kind(), "Config", "{} {:?}", key, values);
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn only_plain_assignments_have_a_key() {
+        assert_eq!(assigned_key("stale_days = 90"), Some("stale_days"));
+        assert_eq!(assigned_key("\"remote\" = \"x\""), Some("remote"));
+        assert_eq!(assigned_key("# stale_days = 90"), None);
+        assert_eq!(assigned_key("[table]"), None);
+        assert_eq!(assigned_key("a.b = 1"), None);
+    }
+}
@@ -0,0 +1,246 @@
+use git2::Time;
+
+/// Converts days since the unix epoch into a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse, valid for the proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) civil date into days since the unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Parses a YYYY-MM-DD date into days since the unix epoch.
+pub fn parse_date(text: &str) -> Result<i64, String> {
+    let invalid = || format!("'{}' is not a YYYY-MM-DD date", text);
+    let parts: Vec<&str> = text.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    let digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    if !digits(year, 4) || !digits(month, 2) || !digits(day, 2) {
+        return Err(invalid());
+    }
+    let year: i64 = year.parse().map_err(|_| invalid())?;
+    let month: u32 = month.parse().map_err(|_| invalid())?;
+    let day: u32 = day.parse().map_err(|_| invalid())?;
+    let days = days_from_civil(year, month, day);
+    // Out of range months and days, such as 02-30, don't survive the round trip
+    if !(1..=12).contains(&month) || civil_from_days(days) != (year, month, day) {
+        return Err(invalid());
+    }
+    Ok(days)
+}
+
+/// Day of a git timestamp in the committer's own timezone, as days since the unix epoch.
+pub fn local_day(time: &Time) -> i64 {
+    (time.seconds() + i64::from(time.offset_minutes()) * 60).div_euclid(86_400)
+}
+
+/// Formats a git timestamp as an ISO-8601 date in the committer's own timezone.
+pub fn format_date(time: &Time) -> String {
+    let (year, month, day) = civil_from_days(local_day(time));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats a git timestamp as its year and month, e.g. "2023-02", in the committer's timezone.
+pub fn format_month(time: &Time) -> String {
+    format_date(time)[..7].to_string()
+}
+
+/// Formats seconds since the unix epoch as an ISO-8601 UTC timestamp, e.g. "2023-02-14T09:30:00Z".
+pub fn format_timestamp(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let secs = seconds.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Returns the current time as seconds since the unix epoch.
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Number of whole days between a git timestamp and `now`.
+pub fn age_days(time: &Time, now: i64) -> i64 {
+    (now - time.seconds()).div_euclid(86_400)
+}
+
+/// A number of days from the command line as an age to compare with `age_days`, counts
+/// past what an age can hold mean no branch is that old.
+pub fn days(count: u64) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+/// Describes how long ago a git timestamp was, the way `git log --date=relative` does, e.g.
+/// "3 weeks ago": in minutes and hours within a day, in days up to two weeks, then weeks,
+/// months and years.
+pub fn format_relative(time: &Time, now: i64) -> String {
+    let seconds = now - time.seconds();
+    let days = age_days(time, now);
+    let (count, unit) = match seconds {
+        i64::MIN..=-1 => return "in the future".to_string(),
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86_399 => (seconds / 3600, "hour"),
+        _ => match days {
+            1..=13 => (days, "day"),
+            14..=69 => (days / 7, "week"),
+            70..=364 => (days / 30, "month"),
+            _ => (days / 365, "year"),
+        },
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates_round_trip_around_the_epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        for days in -800_000..800_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn leap_days_follow_the_gregorian_rules() {
+        assert_eq!(parse_date("2024-02-29"), Ok(19_782));
+        assert_eq!(parse_date("2000-02-29"), Ok(11_016));
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("1900-02-29").is_err());
+    }
+
+    #[test]
+    fn dates_that_do_not_exist_are_rejected() {
+        for text in [
+            "2021-02-30",
+            "2021-04-31",
+            "2021-13-01",
+            "2021-00-10",
+            "2021-01-00",
+        ] {
+            assert!(parse_date(text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn only_yyyy_mm_dd_is_parsed() {
+        assert_eq!(parse_date("1970-01-01"), Ok(0));
+        assert_eq!(parse_date("1969-12-31"), Ok(-1));
+        for text in [
+            "2021-1-05",
+            "21-01-05",
+            "2021/01/05",
+            "2021-01-05T00:00",
+            "",
+            "+202-01-05",
+        ] {
+            assert!(parse_date(text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn dates_are_in_the_committers_timezone() {
+        // 23:30 UTC on the last day of 1969 is already 1970 an hour east of it
+        let time = Time::new(-1800, 60);
+        assert_eq!(local_day(&time), 0);
+        assert_eq!(format_date(&time), "1970-01-01");
+        assert_eq!(format_date(&Time::new(-1800, 0)), "1969-12-31");
+        assert_eq!(format_date(&Time::new(1800, -60)), "1969-12-31");
+        assert_eq!(format_month(&Time::new(1_676_366_400, 0)), "2023-02");
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+        assert_eq!(format_timestamp(1_676_367_000), "2023-02-14T09:30:00Z");
+    }
+
+    #[test]
+    fn day_counts_saturate() {
+        assert_eq!(days(90), 90);
+        assert_eq!(days(i64::MAX as u64), i64::MAX);
+        assert_eq!(days(u64::MAX), i64::MAX);
+    }
+
+    #[test]
+    fn ages_are_whole_days() {
+        let time = Time::new(0, 0);
+        assert_eq!(age_days(&time, 86_399), 0);
+        assert_eq!(age_days(&time, 86_400), 1);
+        assert_eq!(age_days(&time, -1), -1);
+    }
+
+    #[test]
+    fn relative_ages_change_unit_at_the_boundaries() {
+        let now = 1_700_000_000;
+        let ago = |seconds: i64| format_relative(&Time::new(now - seconds, 0), now);
+        let day = 86_400;
+        for (seconds, expected) in [
+            (0, "just now"),
+            (59, "just now"),
+            (60, "1 minute ago"),
+            (3599, "59 minutes ago"),
+            (3600, "1 hour ago"),
+            (day - 1, "23 hours ago"),
+            (day, "1 day ago"),
+            (13 * day, "13 days ago"),
+            (14 * day, "2 weeks ago"),
+            (69 * day, "9 weeks ago"),
+            (70 * day, "2 months ago"),
+            (364 * day, "12 months ago"),
+            (365 * day, "1 year ago"),
+            (3 * 365 * day, "3 years ago"),
+        ] {
+            assert_eq!(ago(seconds), expected, "{} seconds", seconds);
+        }
+    }
+
+    #[test]
+    fn future_timestamps_are_not_ages() {
+        let now = 1_700_000_000;
+        assert_eq!(
+            format_relative(&Time::new(now + 1, 0), now),
+            "in the future"
+        );
+        assert_eq!(
+            format_relative(&Time::new(now + 400 * 86_400, 0), now),
+            "in the future"
+        );
+    }
+}
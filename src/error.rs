@@ -0,0 +1,109 @@
+//! Failures callers can tell apart, by exit status and by the kind `--error-format json`
+//! reports.
+//!
+//! The subcommands and the helpers below them return a `HarvestError`. Failures from the
+//! libraries underneath become `Other` unless the call site knows better. The kind names
+//! are part of the output scripts rely on, so they only ever get added to.
+
+use crate::auth;
+use git2::ErrorCode;
+use thiserror::Error;
+
+/// Exit status of a cleanup or prune-local that ran but could not delete every branch
+pub const EXIT_DELETE_FAILED: i32 = 3;
+
+/// Exit status of a stale run finding more stale branches than --fail-if allows
+pub const EXIT_TOO_MANY_STALE: i32 = 4;
+
+#[derive(Debug, Error)]
+pub enum HarvestError {
+    #[error("'{0}' is not a git repository")]
+    NotARepo(String),
+    /// A config file, or the git configuration, can't be read or written
+    #[error("{0}")]
+    Config(String),
+    /// The remote to work on is missing, with what to do about it
+    #[error("{0}")]
+    NoRemote(String),
+    /// The server refused the credentials
+    #[error("{0}")]
+    AuthFailed(String),
+    /// A branch or commit named on the command line, or the default branch, is not there
+    #[error("{0}")]
+    BranchNotFound(String),
+    /// The filter flags can't select anything, or don't parse
+    #[error("{0}")]
+    InvalidFilter(String),
+    /// A branch could not be deleted
+    #[error("{0}")]
+    DeleteFailed(String),
+    /// Some deletions failed, they were reported one by one as they happened
+    #[error("{0} branches could not be deleted")]
+    DeletionFailures(usize),
+    /// More branches are stale than --fail-if allows: how many are, and the limit
+    #[error("{0} branches are stale, more than the {1} allowed by --fail-if")]
+    TooManyStale(usize, usize),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl HarvestError {
+    /// The stable name of the variant, as --error-format json prints it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HarvestError::NotARepo(_) => "NotARepo",
+            HarvestError::Config(_) => "Config",
+            HarvestError::NoRemote(_) => "NoRemote",
+            HarvestError::AuthFailed(_) => "AuthFailed",
+            HarvestError::BranchNotFound(_) => "BranchNotFound",
+            HarvestError::InvalidFilter(_) => "InvalidFilter",
+            HarvestError::DeleteFailed(_) => "DeleteFailed",
+            HarvestError::DeletionFailures(_) => "DeletionFailures",
+            HarvestError::TooManyStale(..) => "TooManyStale",
+            HarvestError::Other(_) => "Other",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HarvestError::DeletionFailures(_) => EXIT_DELETE_FAILED,
+            HarvestError::TooManyStale(..) => EXIT_TOO_MANY_STALE,
+            _ => 1,
+        }
+    }
+}
+
+/// Plain messages, and the errors of the libraries below, become Other
+macro_rules! other_from {
+    ($($source:ty),*) => {
+        $(
+            impl From<$source> for HarvestError {
+                fn from(err: $source) -> HarvestError {
+                    HarvestError::Other(err.to_string())
+                }
+            }
+        )*
+    };
+}
+
+other_from!(
+    String,
+    &str,
+    git2::Error,
+    std::io::Error,
+    serde_json::Error,
+    csv::Error,
+    dialoguer::Error,
+    globset::Error,
+    rayon::ThreadPoolBuildError
+);
+
+/// A failure talking to a remote, an AuthFailed when the credentials were refused
+pub fn remote_failure(context: String, err: &git2::Error) -> HarvestError {
+    let message = format!("{}: {}", context, auth::describe_error(err));
+    if err.code() == ErrorCode::Auth {
+        HarvestError::AuthFailed(message)
+    } else {
+        HarvestError::Other(message)
+    }
+}
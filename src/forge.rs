@@ -0,0 +1,308 @@
+//! Open pull and merge requests from GitHub and GitLab, for `--check-prs`.
+//!
+//! The host and project are parsed from the remote's URL and the API is queried with
+//! `curl`, so no HTTP stack is linked in. Tokens are handed to curl on stdin rather than
+//! on its command line, where other users could read them.
+
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Results asked for per page, the maximum both APIs allow
+const PAGE_SIZE: usize = 100;
+
+/// Stops paging through a project with an unreasonable number of open requests
+const MAX_PAGES: usize = 50;
+
+#[derive(Debug, PartialEq)]
+enum Forge {
+    GitHub,
+    GitLab,
+}
+
+/// A project on a forge, as found in a remote URL
+#[derive(Debug)]
+pub struct Project {
+    forge: Forge,
+    host: String,
+    path: String,
+}
+
+/// Splits ssh, scp-like and http(s) remote URLs into host and project path.
+fn host_and_path(url: &str) -> Option<(String, String)> {
+    let (host, path) = if let Some((_, rest)) = url.split_once("://") {
+        let (authority, path) = rest.split_once('/')?;
+        let host = authority.rsplit('@').next()?;
+        // A port belongs to the transport, not to the API host
+        let host = host.split(':').next()?;
+        (host, path)
+    } else {
+        // scp-like syntax, e.g. git@github.com:owner/repo.git
+        let (authority, path) = url.split_once(':')?;
+        (authority.rsplit('@').next()?, path)
+    };
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() || !path.contains('/') {
+        return None;
+    }
+    Some((host.to_lowercase(), path.to_string()))
+}
+
+impl Project {
+    /// The project a remote URL points at, when it is hosted on GitHub, on gitlab.com or on
+    /// one of the self-hosted GitLab hosts given. Tokens go wherever this says the project
+    /// is, so a host is never taken for a GitLab by its name.
+    pub fn from_url(url: &str, gitlab_hosts: &[String]) -> Option<Project> {
+        let (host, path) = host_and_path(url)?;
+        let forge = if host == "github.com" {
+            Forge::GitHub
+        } else if host == "gitlab.com"
+            || gitlab_hosts
+                .iter()
+                .any(|gitlab| gitlab.eq_ignore_ascii_case(&host))
+        {
+            Forge::GitLab
+        } else {
+            return None;
+        };
+        Some(Project { forge, host, path })
+    }
+
+    pub fn is_github(&self) -> bool {
+        self.forge == Forge::GitHub
+    }
+
+    /// What an open request is called on this forge, e.g. "PR #12" or "MR !12"
+    pub fn label(&self, number: u64) -> String {
+        match self.forge {
+            Forge::GitHub => format!("PR #{}", number),
+            Forge::GitLab => format!("MR !{}", number),
+        }
+    }
+
+    /// Open requests by the name of the branch they would merge from this project.
+    /// Requests from forks are left out, their branches are not the remote's.
+    pub fn open_requests(&self, token: Option<&str>) -> Result<HashMap<String, u64>, String> {
+        let mut requests = HashMap::new();
+        for page in 1..=MAX_PAGES {
+            let items = self.fetch_page(page, token)?;
+            let count = items.len();
+            for item in items {
+                if let Some((branch, number)) = self.parse_request(&item) {
+                    // Keep the oldest when several requests come from one branch
+                    requests
+                        .entry(branch)
+                        .and_modify(|existing: &mut u64| *existing = (*existing).min(number))
+                        .or_insert(number);
+                }
+            }
+            if count < PAGE_SIZE {
+                break;
+            }
+        }
+        debug!(
+            "{} branches of {} have an open request",
+            requests.len(),
+            self.path
+        );
+        Ok(requests)
+    }
+
+    fn fetch_page(&self, page: usize, token: Option<&str>) -> Result<Vec<Value>, String> {
+        let (url, header) = match self.forge {
+            Forge::GitHub => {
+                // GitHub Actions and Enterprise point this at their own API
+                let api = std::env::var("GITHUB_API_URL")
+                    .unwrap_or_else(|_| "https://api.github.com".to_string());
+                let url = format!(
+                    "{}/repos/{}/pulls?state=open&per_page={}&page={}",
+                    api.trim_end_matches('/'),
+                    self.path,
+                    PAGE_SIZE,
+                    page
+                );
+                (
+                    url,
+                    token.map(|token| format!("Authorization: Bearer {}", token)),
+                )
+            }
+            Forge::GitLab => {
+                let url = format!(
+                    "https://{}/api/v4/projects/{}/merge_requests?state=opened&per_page={}&page={}",
+                    self.host,
+                    self.path.replace('/', "%2F"),
+                    PAGE_SIZE,
+                    page
+                );
+                (url, token.map(|token| format!("PRIVATE-TOKEN: {}", token)))
+            }
+        };
+        debug!("fetching {}", url);
+        let body = curl(&url, header.as_deref())?;
+        match serde_json::from_str(&body) {
+            Ok(Value::Array(items)) => Ok(items),
+            _ => Err(format!("unexpected answer from {}", self.host)),
+        }
+    }
+
+    fn parse_request(&self, item: &Value) -> Option<(String, u64)> {
+        match self.forge {
+            Forge::GitHub => {
+                let head = &item["head"];
+                let same_repo = head["repo"]["full_name"]
+                    .as_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(&self.path));
+                let branch = head["ref"].as_str().filter(|_| same_repo)?;
+                Some((branch.to_string(), item["number"].as_u64()?))
+            }
+            Forge::GitLab => {
+                if item["source_project_id"] != item["target_project_id"] {
+                    return None;
+                }
+                let branch = item["source_branch"].as_str()?;
+                Some((branch.to_string(), item["iid"].as_u64()?))
+            }
+        }
+    }
+}
+
+/// GETs a URL with curl, sending the optional header through stdin. Redirects are not
+/// followed, curl would send the header on to wherever they lead.
+fn curl(url: &str, header: Option<&str>) -> Result<String, String> {
+    let mut command = Command::new("curl");
+    command
+        .args(["--silent", "--show-error", "--fail"])
+        .args(["--max-time", "20"])
+        .args(["--header", "Accept: application/json"])
+        .args(["--header", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("unable to run curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = writeln!(stdin, "{}", header.unwrap_or_default());
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("unable to run curl: {}", e))?;
+    if !output.status.success() {
+        // curl says e.g. "curl: (22) The requested URL returned error: 401"
+        let message = String::from_utf8_lossy(&output.stderr);
+        let message = message.trim().trim_start_matches("curl: ");
+        let message = match message.split_once(") ") {
+            Some((code, rest)) if code.starts_with('(') => rest,
+            _ => message,
+        };
+        return Err(message.to_string());
+    }
+    String::from_utf8(output.stdout).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn project(url: &str) -> Option<(Forge, String, String)> {
+        Project::from_url(url, &["git.corp.example".to_string()])
+            .map(|project| (project.forge, project.host, project.path))
+    }
+
+    #[test]
+    fn ssh_https_and_scp_urls_are_parsed() {
+        let expected = Some((
+            Forge::GitHub,
+            "github.com".to_string(),
+            "owner/repo".to_string(),
+        ));
+        for url in [
+            "https://github.com/owner/repo.git",
+            "https://user@github.com/owner/repo",
+            "ssh://git@github.com:22/owner/repo.git",
+            "git@github.com:owner/repo.git",
+            "git@GitHub.com:owner/repo/",
+        ] {
+            assert_eq!(project(url), expected, "{}", url);
+        }
+        assert_eq!(
+            project("git@gitlab.com:group/sub/repo.git"),
+            Some((
+                Forge::GitLab,
+                "gitlab.com".to_string(),
+                "group/sub/repo".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn urls_without_a_project_are_not_parsed() {
+        for url in [
+            "https://github.com/",
+            "git@github.com:repo.git",
+            "/srv/git/repo.git",
+        ] {
+            assert_eq!(project(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn gitlab_is_recognised_by_host_not_by_name() {
+        assert_eq!(
+            project("https://git.corp.example/team/repo.git").map(|(forge, ..)| forge),
+            Some(Forge::GitLab)
+        );
+        for url in [
+            "https://gitlab.attacker.example/team/repo.git",
+            "git@notgitlab.com:team/repo.git",
+            "https://gitlab.com.attacker.example/team/repo.git",
+            "https://bitbucket.org/team/repo.git",
+        ] {
+            assert_eq!(project(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn requests_from_forks_are_left_out() {
+        let github = Project::from_url("git@github.com:owner/repo.git", &[]).unwrap();
+        let own = json!({"number": 7, "head": {"ref": "fix", "repo": {"full_name": "Owner/Repo"}}});
+        let fork = json!({"number": 8, "head": {"ref": "fix", "repo": {"full_name": "fork/repo"}}});
+        assert_eq!(github.parse_request(&own), Some(("fix".to_string(), 7)));
+        assert_eq!(github.parse_request(&fork), None);
+        assert_eq!(github.label(7), "PR #7");
+
+        let gitlab = Project::from_url("git@gitlab.com:group/repo.git", &[]).unwrap();
+        let own = json!({
+            "iid": 3,
+            "source_branch": "fix",
+            "source_project_id": 1,
+            "target_project_id": 1
+        });
+        let fork = json!({
+            "iid": 4,
+            "source_branch": "fix",
+            "source_project_id": 2,
+            "target_project_id": 1
+        });
+        assert_eq!(gitlab.parse_request(&own), Some(("fix".to_string(), 3)));
+        assert_eq!(gitlab.parse_request(&fork), None);
+        assert_eq!(gitlab.label(3), "MR !3");
+    }
+
+    #[test]
+    fn an_unreachable_forge_is_an_error_not_a_panic() {
+        // Nothing listens on the discard port, curl fails at once whether or not it is installed
+        let project = Project {
+            forge: Forge::GitLab,
+            host: "127.0.0.1:9".to_string(),
+            path: "group/repo".to_string(),
+        };
+        assert!(project.open_requests(None).is_err());
+        assert!(project.open_requests(Some("token")).is_err());
+    }
+}
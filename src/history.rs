@@ -0,0 +1,60 @@
+//! The SQLite history `stats --sqlite` appends to, built with the `sqlite` feature.
+//!
+//! Each run adds a row to `runs` with its time and branch total, and a row per author to
+//! `author_counts`, so trends can be queried over weeks, e.g.
+//!
+//! ```sql
+//! SELECT date(timestamp, 'unixepoch'), total_branches FROM runs ORDER BY timestamp;
+//! ```
+
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    repositories TEXT NOT NULL,
+    total_branches INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS author_counts (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    email TEXT NOT NULL,
+    branches INTEGER NOT NULL,
+    PRIMARY KEY (run_id, email)
+);
+";
+
+/// Records a run in the database at `path`, creating the file and its tables when missing.
+/// `timestamp` is in seconds since the epoch and `repositories` the locations counted.
+pub fn record(
+    path: &Path,
+    timestamp: i64,
+    repositories: &[String],
+    total: usize,
+    per_author: &[(String, usize)],
+) -> Result<(), String> {
+    let failed = |e: rusqlite::Error| format!("unable to write '{}': {}", path.display(), e);
+    let mut connection = Connection::open(path).map_err(failed)?;
+    connection.execute_batch(SCHEMA).map_err(failed)?;
+    // All of a run or none of it
+    let transaction = connection.transaction().map_err(failed)?;
+    transaction
+        .execute(
+            "INSERT INTO runs (timestamp, repositories, total_branches) VALUES (?1, ?2, ?3)",
+            params![timestamp, repositories.join("\n"), total as i64],
+        )
+        .map_err(failed)?;
+    let run = transaction.last_insert_rowid();
+    {
+        let mut insert = transaction
+            .prepare("INSERT INTO author_counts (run_id, email, branches) VALUES (?1, ?2, ?3)")
+            .map_err(failed)?;
+        for (email, count) in per_author {
+            insert
+                .execute(params![run, email, *count as i64])
+                .map_err(failed)?;
+        }
+    }
+    transaction.commit().map_err(failed)
+}
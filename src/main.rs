@@ -1,184 +1,4716 @@
-use clap::{Parser, Subcommand};
-use dialoguer::Confirm;
-use git2::{BranchType, Config, Repository};
-use std::collections::HashMap;
-use std::error::Error;
-use std::path::Path;
-use std::process::{Command, Stdio};
+mod auth;
+#[cfg(feature = "tui")]
+mod browse;
+mod cache;
+mod config;
+mod date;
+mod error;
+mod forge;
+#[cfg(feature = "sqlite")]
+mod history;
+mod pager;
+mod template;
 
-fn get_current_user_info() -> (String, String) {
-    let cfg = Config::open_default().unwrap();
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use colored::Colorize;
+use dialoguer::{Confirm, Input, MultiSelect, Select};
+use error::HarvestError;
+use git2::{
+    BranchType, Commit, Config, ConfigLevel, Direction, ErrorClass, ErrorCode, FetchOptions,
+    FetchPrune, Mailmap, Oid, PushOptions, Reference, Remote, Repository, Sort, Time,
+};
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, LevelFilter};
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use template::{Field, Template};
+
+/// Set by --quiet, drops the banners, notes and summaries around the actual output
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// println! for informational output that --quiet suppresses
+macro_rules! inform {
+    ($($arg:tt)*) => {
+        if !quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// The git configuration as seen from the repository at the location: its own file over the
+/// global and system ones, like git reads them
+fn git_config(location: &str) -> Result<Config, HarvestError> {
+    get_repo(location.to_string())?.config().map_err(|e| {
+        HarvestError::Config(format!("unable to read git configuration: {}", e.message()))
+    })
+}
+
+/// The user's name and email from the git configuration, None for the ones not set
+fn get_current_user_info(location: &str) -> Result<(Option<String>, Option<String>), HarvestError> {
+    let cfg = git_config(location)?;
 
     // Retrieve the user's name and email from the configuration
-    let user_name = cfg
-        .get_string("user.name")
-        .unwrap_or_else(|_| "Unknown User".to_string());
-    let user_email = cfg
-        .get_string("user.email")
-        .unwrap_or_else(|_| "Unknown Email".to_string());
+    let user_name = cfg.get_string("user.name").ok();
+    let user_email = cfg.get_string("user.email").ok();
+
+    Ok((user_name, user_email))
+}
+
+/// The git configuration file a key is read from, None when the key is not set
+fn config_origin(cfg: &Config, key: &str, location: &str) -> Option<String> {
+    let entry = cfg.get_entry(key).ok()?;
+    let path = match entry.level() {
+        ConfigLevel::Local => get_repo(location.to_string())
+            .ok()
+            .map(|repo| repo.path().join("config")),
+        ConfigLevel::Global => Config::find_global().ok(),
+        ConfigLevel::XDG => Config::find_xdg().ok(),
+        ConfigLevel::System => Config::find_system().ok(),
+        _ => None,
+    };
+    Some(match path {
+        Some(path) => path.display().to_string(),
+        None => format!("{:?} git configuration", entry.level()).to_lowercase(),
+    })
+}
+
+fn whoami(location: &str) -> Result<(), HarvestError> {
+    // Outside a repository only the global and system files apply, which is worth saying
+    let cfg = match git_config(location) {
+        Err(HarvestError::NotARepo(path)) => {
+            inform!(
+                "'{}' is not a git repository, showing the global configuration",
+                path
+            );
+            Config::open_default().map_err(|e| {
+                HarvestError::Config(format!("unable to read git configuration: {}", e.message()))
+            })?
+        }
+        cfg => cfg?,
+    };
+    let user_name = cfg.get_string("user.name").ok();
+    let user_email = cfg.get_string("user.email").ok();
+    let user_name = user_name.unwrap_or_else(|| "Unknown User".to_string());
+    let user_email = user_email.unwrap_or_else(|| "Unknown Email".to_string());
+    for (key, value) in [("user.name", user_name), ("user.email", user_email)] {
+        let origin = match config_origin(&cfg, key, location) {
+            Some(origin) => format!("from {}", origin),
+            None => format!("not set, run `git config --global {} ...`", key),
+        };
+        println!("{}: {}  {}", key, value.cyan(), origin.dimmed());
+    }
+    inform!("list and cleanup filter on user.email unless --email is given");
+    Ok(())
+}
+
+/// config get and config set, on the repository's file unless --user is given
+fn config_command(location: &str, action: &ConfigAction) -> Result<(), HarvestError> {
+    let repo_path = repo_root(location).join(config::FILE_NAME);
+    let user_path = || -> Result<PathBuf, HarvestError> {
+        config::user_path()
+            .ok_or_else(|| "unable to locate the user config file, HOME is not set".into())
+    };
+    match action {
+        ConfigAction::Get { key, user } => {
+            let settings = if *user {
+                config::load_user()?
+            } else {
+                config::load_repo(&repo_root(location))?.or(config::load_user()?)
+            };
+            match config::get(&settings, key)? {
+                Some(values) => values.iter().for_each(|value| println!("{}", value)),
+                None => return Err(format!("{} is not set", key).into()),
+            }
+        }
+        ConfigAction::Set { key, values, user } => {
+            if key == "location" && !user {
+                return Err("location is only honored in the user file, pass --user".into());
+            }
+            let path = if *user { user_path()? } else { repo_path };
+            config::set(&path, key, values)?;
+            inform!("{} set in '{}'", key, path.display());
+        }
+    }
+    Ok(())
+}
+
+/// The git repositories directly inside the directory, in name order
+fn scan_repositories(dir: &str) -> Result<Vec<String>, HarvestError> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("unable to read directory '{}': {}", dir, e))?;
+    let mut locations: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && Repository::open(path).is_ok())
+        .map(|path| path.display().to_string())
+        .collect();
+    if locations.is_empty() {
+        return Err(format!("no git repositories found in '{}'", dir).into());
+    }
+    locations.sort();
+    debug!("found {} repositories in '{}'", locations.len(), dir);
+    Ok(locations)
+}
+
+/// Opens the repository the path is in, searching its parents like git does. The current
+/// directory also honours GIT_DIR and the other GIT_* discovery variables.
+fn get_repo(path: String) -> Result<Repository, HarvestError> {
+    let repo = if path == "." {
+        Repository::open_from_env()
+    } else {
+        Repository::discover(&path)
+    };
+    repo.map_err(|_| HarvestError::NotARepo(path))
+}
+
+/// Directory holding the repository's .git-harvest.toml: the top of the working tree, or
+/// the repository itself when bare. The location is used as is when it is not a repository.
+fn repo_root(location: &str) -> PathBuf {
+    match get_repo(location.to_string()) {
+        Ok(repo) => repo.workdir().unwrap_or(repo.path()).to_path_buf(),
+        Err(_) => PathBuf::from(location),
+    }
+}
+
+struct BranchInfo {
+    name: String,
+    kind: BranchType,
+    // The identity picked with --by, the author unless asked otherwise
+    author_name: String,
+    author_email: String,
+    // Who last committed the tip, which rebases and cherry-picks set apart from the author
+    committer_name: String,
+    committer_email: String,
+    time: Time,
+    tip: Oid,
+}
+
+impl BranchInfo {
+    /// "author <email>", followed by "; committed-by committer <email>" when someone else
+    /// committed the tip
+    fn display_identity(&self) -> String {
+        let author = format!("{} <{}>", self.author_name, self.author_email);
+        if self.committer_name == self.author_name
+            && self
+                .committer_email
+                .eq_ignore_ascii_case(&self.author_email)
+        {
+            return author;
+        }
+        format!(
+            "{}; committed-by {} <{}>",
+            author, self.committer_name, self.committer_email
+        )
+    }
+
+    fn display_name(&self) -> String {
+        match self.kind {
+            BranchType::Local => format!("{} (local)", self.name),
+            BranchType::Remote => self.name.clone(),
+        }
+    }
+}
+
+/// Bare repositories (server-side copies, CI mirrors) usually have no remote-tracking
+/// branches, their heads are the branches
+fn uses_bare_heads(repo: &Repository, remote: &str) -> bool {
+    if !repo.is_bare() {
+        return false;
+    }
+    let remote_prefix = format!("refs/remotes/{}/*", remote);
+    let has_tracking = repo
+        .references_glob(&remote_prefix)
+        .map(|mut references| references.next().is_some())
+        .unwrap_or(false);
+    !has_tracking
+}
+
+/// Which identity of a branch's last commit the branch is attributed to
+#[derive(Clone, Copy, ValueEnum)]
+enum Identity {
+    Author,
+    Committer,
+}
+
+/// Key of a branch in the --cache file, local and remote branches can share a name
+fn cache_key(name: &str, kind: BranchType) -> String {
+    match kind {
+        BranchType::Local => format!("local:{}", name),
+        BranchType::Remote => format!("remote:{}", name),
+    }
+}
+
+/// The name a remote-tracking branch has on the remote, None when it tracks another remote.
+/// The fetch refspecs of the configured remotes tell which one a branch belongs to, the most
+/// specific one winning, so remote names containing a slash such as "team" and "team/ci" are
+/// told apart, and refspecs storing branches elsewhere than refs/remotes/<remote>/ are followed.
+fn remote_branch_name(
+    remotes: &[Remote],
+    remote: &str,
+    refname: &str,
+    shorthand: &str,
+) -> Option<String> {
+    let owner = remotes
+        .iter()
+        .flat_map(|owner| {
+            owner
+                .refspecs()
+                .filter(|spec| spec.direction() == Direction::Fetch && spec.dst_matches(refname))
+                .map(move |spec| (owner, spec))
+        })
+        .max_by_key(|(_, spec)| spec.dst_bytes().len());
+    let Some((owner, spec)) = owner else {
+        // No remote claims the branch, its name is all there is to go on
+        return shorthand
+            .strip_prefix(&format!("{}/", remote))
+            .map(str::to_string);
+    };
+    if owner.name() != Some(remote) {
+        return None;
+    }
+    let source = spec.rtransform(refname).ok()?;
+    source
+        .as_str()?
+        .strip_prefix("refs/heads/")
+        .map(str::to_string)
+}
+
+fn get_remote_branches(
+    repo: &Repository,
+    remote: &str,
+    include_local: bool,
+    include_symbolic: bool,
+    by: Identity,
+    cache_path: Option<&Path>,
+) -> Result<Vec<BranchInfo>, HarvestError> {
+    let remotes: Vec<Remote> = match repo.remotes() {
+        Ok(names) => names
+            .iter()
+            .flatten()
+            .filter_map(|name| repo.find_remote(name).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let mut kinds = vec![BranchType::Remote];
+    if include_local || uses_bare_heads(repo, remote) {
+        kinds.push(BranchType::Local);
+    }
+
+    // Reading the refs is cheap, loading every tip commit is what takes time
+    let mut tips = Vec::new();
+    for kind in kinds {
+        // List all branches of this kind
+        let branches = repo.branches(Some(kind)).map_err(|e| {
+            HarvestError::Other(format!("unable to list the branches: {}", e.message()))
+        })?;
+        for branch_result in branches {
+            let Ok((branch, _)) = branch_result else {
+                continue;
+            };
+            // Names that aren't UTF-8 can't be matched or shown, they are skipped
+            let branch_name = match (branch.name().ok().flatten(), kind) {
+                // Exclude the remote part from the branch name, skipping other remotes
+                (Some(name), BranchType::Remote) => {
+                    let refname = branch.get().name().unwrap_or_default();
+                    match remote_branch_name(&remotes, remote, refname, name) {
+                        Some(name) => name,
+                        None => continue,
+                    }
+                }
+                (Some(name), BranchType::Local) => name.to_string(),
+                (None, _) => continue,
+            };
+
+            // Symbolic refs such as origin/HEAD are another name for the branch they point at
+            if branch.get().symbolic_target_bytes().is_some() && !include_symbolic {
+                debug!("{} is a symbolic ref, skipped", branch_name);
+                continue;
+            }
+            // Resolving looks the ref up again by name, only symbolic ones need it
+            let tip = match branch.get().target() {
+                Some(tip) => tip,
+                None => match branch.get().resolve().ok().and_then(|r| r.target()) {
+                    Some(tip) => tip,
+                    None => continue,
+                },
+            };
+            tips.push((branch_name, kind, tip));
+        }
+    }
+
+    // Tips that did not move since a run with the same --cache are not loaded again
+    let mut cache = cache_path.map(cache::Cache::load);
+    let repository_key = format!(
+        "{}#{}",
+        repo.path().display(),
+        match by {
+            Identity::Author => "author",
+            Identity::Committer => "committer",
+        }
+    );
+
+    let load = |repo: &Repository, chunk: &[(String, BranchType, Oid)]| {
+        // Aliases from .mailmap resolve to the contributor's canonical identity
+        let mailmap = repo.mailmap().ok();
+        let mut loaded = Vec::with_capacity(chunk.len());
+        for (name, kind, tip) in chunk {
+            let (name, kind, tip) = (name.clone(), *kind, *tip);
+            let cached = cache.as_ref().and_then(|cache| {
+                cache.get(&repository_key, &cache_key(&name, kind), &tip.to_string())
+            });
+            if let Some(entry) = cached {
+                loaded.push(BranchInfo {
+                    author_name: entry.name.clone(),
+                    author_email: entry.email.clone(),
+                    committer_name: entry.committer_name.clone(),
+                    committer_email: entry.committer_email.clone(),
+                    time: Time::new(entry.time, entry.offset),
+                    name,
+                    kind,
+                    tip,
+                });
+                continue;
+            }
+            // Refs may point at an annotated tag, which leads to a commit, or at a tree or a
+            // blob, which don't
+            let commit = repo
+                .find_object(tip, None)
+                .and_then(|object| object.peel_to_commit());
+            match commit {
+                Ok(commit) => loaded.push(branch_info(&commit, mailmap.as_ref(), name, kind, by)),
+                Err(_) => debug!("{}: {} is not a commit, skipped", name, tip),
+            }
+        }
+        loaded
+    };
+
+    // Repository handles can't be shared across threads, the tips are split into one chunk
+    // per worker so that each opens the repository and reads the mailmap once. A single
+    // worker keeps the handle that listed the refs, whose caches are already warm.
+    let workers = rayon::current_num_threads().min(tips.len()).max(1);
+    let branch_info: Vec<BranchInfo> = if workers == 1 {
+        load(repo, &tips)
+    } else {
+        let path = repo.path().to_path_buf();
+        let chunks: Vec<Vec<BranchInfo>> = tips
+            .par_chunks(tips.len().div_ceil(workers))
+            .map(|chunk| {
+                let repo = Repository::open(&path).map_err(|e| {
+                    HarvestError::Other(format!(
+                        "unable to open '{}' to read the branches: {}",
+                        path.display(),
+                        e.message()
+                    ))
+                })?;
+                Ok::<_, HarvestError>(load(&repo, chunk))
+            })
+            .collect::<Result<_, _>>()?;
+        chunks.into_iter().flatten().collect()
+    };
+
+    if let (Some(cache), Some(path)) = (cache.as_mut(), cache_path) {
+        let entries = branch_info
+            .iter()
+            .map(|branch| {
+                let entry = cache::Entry {
+                    tip: branch.tip.to_string(),
+                    name: branch.author_name.clone(),
+                    email: branch.author_email.clone(),
+                    committer_name: branch.committer_name.clone(),
+                    committer_email: branch.committer_email.clone(),
+                    time: branch.time.seconds(),
+                    offset: branch.time.offset_minutes(),
+                };
+                (cache_key(&branch.name, branch.kind), entry)
+            })
+            .collect();
+        cache.replace(repository_key, entries);
+        if let Err(err) = cache.save(path) {
+            if !quiet() {
+                eprintln!("{}", format!("note: {}", err).yellow());
+            }
+        }
+    }
+
+    debug!(
+        "enumerated {} branches of remote '{}'{}",
+        branch_info.len(),
+        remote,
+        if include_local { " and local" } else { "" }
+    );
+    Ok(branch_info)
+}
+
+/// Describes a branch by its last commit, attributed to the identity picked with --by
+fn branch_info(
+    commit: &Commit,
+    mailmap: Option<&Mailmap>,
+    name: String,
+    kind: BranchType,
+    by: Identity,
+) -> BranchInfo {
+    let author = match (by, mailmap) {
+        (Identity::Author, Some(mailmap)) => commit
+            .author_with_mailmap(mailmap)
+            .unwrap_or_else(|_| commit.author().to_owned()),
+        (Identity::Author, None) => commit.author().to_owned(),
+        (Identity::Committer, Some(mailmap)) => commit
+            .committer_with_mailmap(mailmap)
+            .unwrap_or_else(|_| commit.committer().to_owned()),
+        (Identity::Committer, None) => commit.committer().to_owned(),
+    };
+    let committer = match mailmap {
+        Some(mailmap) => commit
+            .committer_with_mailmap(mailmap)
+            .unwrap_or_else(|_| commit.committer().to_owned()),
+        None => commit.committer().to_owned(),
+    };
+    let author_name = author.name().unwrap_or("Unknown");
+    let author_email = author.email().unwrap_or("Unknown");
+    BranchInfo {
+        name,
+        kind,
+        author_name: author_name.to_string(),
+        author_email: author_email.to_string(),
+        committer_name: committer.name().unwrap_or("Unknown").to_string(),
+        committer_email: committer.email().unwrap_or("Unknown").to_string(),
+        time: commit.time(),
+        tip: commit.id(),
+    }
+}
+
+/// Where the branches of every subcommand are read from
+struct Source {
+    location: String,
+    remote: String,
+    include_local: bool,
+    include_symbolic: bool,
+    fetch: bool,
+    by: Identity,
+    default_branch: Option<String>,
+    cache: Option<PathBuf>,
+}
+
+impl Source {
+    /// Commit of the branch that merged detection and ahead counts compare against:
+    /// --default-branch, else the branch the remote's HEAD points at, else main or master
+    fn default_branch(&self, repo: &Repository) -> Result<Oid, HarvestError> {
+        if let Some(spec) = &self.default_branch {
+            return resolve_commit(repo, &self.remote, spec);
+        }
+        let remote_head = format!("refs/remotes/{}/HEAD", self.remote);
+        let head = match repo.find_reference(&remote_head) {
+            Ok(reference) => Some(reference),
+            // A bare copy's heads are the remote's branches, its own HEAD is the remote's
+            Err(_) if uses_bare_heads(repo, &self.remote) => repo.head().ok(),
+            Err(_) => None,
+        };
+        if let Some(commit) = head.and_then(|head| head.peel_to_commit().ok()) {
+            debug!("default branch of '{}' is at {}", self.remote, commit.id());
+            return Ok(commit.id());
+        }
+        for name in ["main", "master"] {
+            if let Ok(commit) = resolve_commit(repo, &self.remote, name) {
+                debug!("no HEAD for remote '{}', using '{}'", self.remote, name);
+                return Ok(commit);
+            }
+        }
+        Err(HarvestError::BranchNotFound(format!(
+            "unable to tell the default branch of remote '{}', it has no HEAD and no main or \
+             master branch, pass --default-branch",
+            self.remote
+        )))
+    }
+
+    /// Opens the repository for reading, noting when the remote has nothing to read
+    fn open(&self) -> Result<Repository, HarvestError> {
+        let repo = self.open_repo()?;
+        if let Some(problem) = self.remote_problem(&repo).filter(|_| !quiet()) {
+            eprintln!("{}", format!("note: {}", problem).yellow());
+        }
+        Ok(repo)
+    }
+
+    fn open_repo(&self) -> Result<Repository, HarvestError> {
+        debug!(
+            "opening repository '{}', using remote '{}'",
+            self.location, self.remote
+        );
+        let repo = get_repo(self.location.clone())?;
+        if self.fetch {
+            fetch_remote(&repo, &self.remote)?;
+        }
+        Ok(repo)
+    }
+
+    /// Explains why no remote branches can be found, if the selected remote is missing
+    fn remote_problem(&self, repo: &Repository) -> Option<String> {
+        if self.include_local
+            || uses_bare_heads(repo, &self.remote)
+            || repo.find_remote(&self.remote).is_ok()
+        {
+            return None;
+        }
+        let remotes = repo.remotes().ok()?;
+        let remotes: Vec<&str> = remotes.iter().flatten().collect();
+        if remotes.is_empty() {
+            Some(
+                "this repository has no remotes, pass --include-local to look at local \
+                 branches or add one with `git remote add`"
+                    .to_string(),
+            )
+        } else {
+            Some(format!(
+                "remote '{}' does not exist, pass --remote with one of: {}",
+                self.remote,
+                remotes.join(", ")
+            ))
+        }
+    }
+
+    fn branches(&self, repo: &Repository) -> Result<Vec<BranchInfo>, HarvestError> {
+        get_remote_branches(
+            repo,
+            &self.remote,
+            self.include_local,
+            self.include_symbolic,
+            self.by,
+            self.cache.as_deref(),
+        )
+    }
+}
+
+/// Branches cleanup refuses to delete unless --no-protect is given
+const PROTECTED_BRANCHES: [&str; 4] = ["main", "master", "develop", "release"];
+
+fn protected_branches(patterns: &[String], no_protect: bool) -> Result<GlobSet, HarvestError> {
+    let mut builder = GlobSetBuilder::new();
+    if !no_protect {
+        for name in PROTECTED_BRANCHES {
+            builder.add(Glob::new(name)?);
+        }
+    }
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| {
+            HarvestError::InvalidFilter(format!("invalid --protect pattern '{}': {}", pattern, e))
+        })?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Exact branch names listed in a --keep-file, one per line, with # starting a comment line
+fn load_keep_file(path: &Path) -> Result<HashSet<String>, HarvestError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("unable to read keep file '{}': {}", path.display(), e))?;
+    let mut kept = HashSet::new();
+    for (number, line) in content.lines().enumerate() {
+        let name = line.trim();
+        if name.is_empty() || name.starts_with('#') {
+            continue;
+        }
+        branch_ref(name)
+            .map_err(|e| format!("keep file '{}' line {}: {}", path.display(), number + 1, e))?;
+        kept.insert(name.to_string());
+    }
+    debug!(
+        "keeping {} branches listed in '{}'",
+        kept.len(),
+        path.display()
+    );
+    Ok(kept)
+}
+
+/// Criteria a branch must satisfy to be listed or cleaned up
+fn email_matches(filter_email: &str, email: &str, contains: bool) -> bool {
+    // Emails are compared case-insensitively, git keeps whatever casing the committer used
+    let filter_email = filter_email.to_lowercase();
+    let email = email.to_lowercase();
+    if contains {
+        email.contains(filter_email.as_str())
+    } else if filter_email.starts_with('@') {
+        email.ends_with(filter_email.as_str())
+    } else {
+        email == filter_email
+    }
+}
+
+/// The --exclude-email value the email matches, if any
+fn excluded_email<'a>(excluded: &'a [String], email: &str) -> Option<&'a String> {
+    excluded
+        .iter()
+        .find(|excluded| email_matches(excluded, email, false))
+}
+
+struct BranchFilter {
+    email: Option<String>,
+    email_contains: bool,
+    email_regex: Option<Regex>,
+    exclude_emails: Vec<String>,
+    committer_email: Option<String>,
+    name: Option<String>,
+    older_than: Option<u64>,
+    newer_than: Option<u64>,
+    pattern: Option<GlobMatcher>,
+    now: i64,
+}
+
+/// Rejects an --older-than/--newer-than window no branch can be in
+fn check_age_window(older_than: Option<u64>, newer_than: Option<u64>) -> Result<(), HarvestError> {
+    match (older_than, newer_than) {
+        (Some(older), Some(newer)) if newer <= older.saturating_add(1) => {
+            Err(HarvestError::InvalidFilter(format!(
+                "no branch is both older than {} days and newer than {} days",
+                older, newer
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether a branch's age in days is within --older-than and --newer-than, both exclusive
+fn in_age_window(age: i64, older_than: Option<u64>, newer_than: Option<u64>) -> bool {
+    older_than.is_none_or(|days| age > date::days(days))
+        && newer_than.is_none_or(|days| age < date::days(days))
+}
+
+impl BranchFilter {
+    /// The filter from the flags, defaulting to the user.email configured for the location
+    /// when `default_email` is set
+    fn new(
+        args: &FilterArgs,
+        location: &str,
+        default_email: bool,
+    ) -> Result<BranchFilter, HarvestError> {
+        check_age_window(args.older_than, args.newer_than)?;
+        // Default to the current user, unless the branches are selected by exclusion, author
+        // name, committer or age
+        let email = match &args.email {
+            Some(email) => Some(email.clone()),
+            None if !default_email
+                || !args.exclude_email.is_empty()
+                || args.committer_email.is_some()
+                || args.name.is_some()
+                || args.older_than.is_some()
+                || args.newer_than.is_some() =>
+            {
+                None
+            }
+            None => Some(get_current_user_info(location)?.1.ok_or_else(|| {
+                HarvestError::Config(
+                    "no user.email in the git configuration to filter on, pass --email or run \
+                     `git config --global user.email <email>`"
+                        .to_string(),
+                )
+            })?),
+        };
+        let pattern = match &args.pattern {
+            Some(pattern) => Some(
+                Glob::new(pattern)
+                    .map_err(|e| {
+                        HarvestError::InvalidFilter(format!(
+                            "invalid --pattern '{}': {}",
+                            pattern, e
+                        ))
+                    })?
+                    .compile_matcher(),
+            ),
+            None => None,
+        };
+        let email_regex = match (&email, args.email_regex) {
+            (Some(email), true) => Some(
+                RegexBuilder::new(email)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| {
+                        HarvestError::InvalidFilter(format!(
+                            "invalid --email regex '{}': {}",
+                            email, e
+                        ))
+                    })?,
+            ),
+            _ => None,
+        };
+        Ok(BranchFilter {
+            email,
+            email_contains: args.email_contains,
+            email_regex,
+            exclude_emails: args.exclude_email.clone(),
+            committer_email: args.committer_email.clone(),
+            name: args.name.as_ref().map(|name| name.to_lowercase()),
+            older_than: args.older_than,
+            newer_than: args.newer_than,
+            pattern,
+            now: date::now(),
+        })
+    }
+
+    /// The criteria in words, for telling what found nothing
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(email) = &self.email {
+            parts.push(match (&self.email_regex, self.email_contains) {
+                (Some(_), _) => format!("email matching /{}/", email),
+                (None, true) => format!("email containing {}", email),
+                (None, false) => format!("email {}", email),
+            });
+        }
+        for excluded in &self.exclude_emails {
+            parts.push(format!("excluding {}", excluded));
+        }
+        if let Some(email) = &self.committer_email {
+            parts.push(format!("committer email {}", email));
+        }
+        if let Some(name) = &self.name {
+            parts.push(format!("author name containing {}", name));
+        }
+        if let Some(days) = self.older_than {
+            parts.push(format!("older than {} days", days));
+        }
+        if let Some(days) = self.newer_than {
+            parts.push(format!("newer than {} days", days));
+        }
+        if let Some(pattern) = &self.pattern {
+            parts.push(format!("name matching '{}'", pattern.glob()));
+        }
+        parts.join(", ")
+    }
+
+    fn matches(&self, branch: &BranchInfo) -> bool {
+        if let Some(excluded) = excluded_email(&self.exclude_emails, &branch.author_email) {
+            debug!(
+                "{}: author {} is excluded by {}",
+                branch.name, branch.author_email, excluded
+            );
+            return false;
+        }
+        if let Some(email) = &self.email {
+            let matches = match &self.email_regex {
+                Some(regex) => regex.is_match(&branch.author_email),
+                None => email_matches(email, &branch.author_email, self.email_contains),
+            };
+            if !matches {
+                debug!(
+                    "{}: author {} does not match {}",
+                    branch.name, branch.author_email, email
+                );
+                return false;
+            }
+        }
+        if let Some(email) = &self.committer_email {
+            if !email_matches(email, &branch.committer_email, false) {
+                debug!(
+                    "{}: committer {} does not match {}",
+                    branch.name, branch.committer_email, email
+                );
+                return false;
+            }
+        }
+        if let Some(name) = &self.name {
+            if !branch.author_name.to_lowercase().contains(name.as_str()) {
+                debug!(
+                    "{}: author {} does not match name {}",
+                    branch.name, branch.author_name, name
+                );
+                return false;
+            }
+        }
+        if let Some(days) = self.older_than {
+            let age = date::age_days(&branch.time, self.now);
+            if age <= date::days(days) {
+                debug!("{}: {} days old, not older than {}", branch.name, age, days);
+                return false;
+            }
+        }
+        if let Some(days) = self.newer_than {
+            let age = date::age_days(&branch.time, self.now);
+            if age >= date::days(days) {
+                debug!("{}: {} days old, not newer than {}", branch.name, age, days);
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(&branch.name) {
+                debug!("{}: does not match pattern {}", branch.name, pattern.glob());
+                return false;
+            }
+        }
+        debug!("{}: matches the filter", branch.name);
+        true
+    }
+}
+
+/// Updates the remote-tracking branches, dropping the ones deleted on the server
+fn fetch_remote(repo: &Repository, remote: &str) -> Result<(), HarvestError> {
+    let mut remote_handle = repo
+        .find_remote(remote)
+        .map_err(|_| HarvestError::NoRemote(format!("remote '{}' does not exist", remote)))?;
+    let config = repo.config()?;
+    let mut options = FetchOptions::new();
+    options
+        .remote_callbacks(auth::remote_callbacks(&config))
+        .prune(FetchPrune::On);
+    debug!("fetching from remote '{}' with prune", remote);
+    remote_handle
+        .fetch(&[] as &[&str], Some(&mut options), None)
+        .map_err(|e| error::remote_failure(format!("failed to fetch from '{}'", remote), &e))?;
+    Ok(())
+}
+
+/// Pushes a single refspec, failing with the reason when the remote refuses it
+fn push_refspec(
+    repo: &Repository,
+    remote: &str,
+    refspec: &str,
+    retries: u32,
+) -> Result<(), PushFailure> {
+    push_refspecs(repo, remote, &[refspec], retries)
+}
+
+/// Pushes several refspecs over one connection, failing with the first reason the remote
+/// gives for refusing one of them. The updates are sent together, but libgit2 can't ask for
+/// an atomic push: a server may still apply some of them and refuse others.
+fn push_refspecs(
+    repo: &Repository,
+    remote: &str,
+    refspecs: &[&str],
+    retries: u32,
+) -> Result<(), PushFailure> {
+    let refspec = refspecs.join(" ");
+    let mut attempt = 0;
+    loop {
+        match push_once(repo, remote, refspecs) {
+            Ok(()) => {
+                if attempt > 0 {
+                    debug!("pushed '{}' after {} retries", refspec, attempt);
+                }
+                return Ok(());
+            }
+            Err(failure) if failure.transient && attempt < retries => {
+                // 0.5s, 1s, 2s, ... between attempts, capped at 32s
+                let backoff = Duration::from_millis(500 << attempt.min(6));
+                attempt += 1;
+                debug!(
+                    "push of '{}' failed: {}, retry {} of {} in {:?}",
+                    refspec, failure.message, attempt, retries, backoff
+                );
+                std::thread::sleep(backoff);
+            }
+            Err(failure) => return Err(failure),
+        }
+    }
+}
+
+/// Why a push failed, whether trying it again could help, and whether it was the
+/// credentials that were refused
+struct PushFailure {
+    message: String,
+    transient: bool,
+    auth: bool,
+}
+
+impl PushFailure {
+    fn permanent(message: String) -> PushFailure {
+        PushFailure {
+            message,
+            transient: false,
+            auth: false,
+        }
+    }
+
+    /// The error to fail with, `context` wrapping the reason in a `kind` error; refused
+    /// credentials are an AuthFailed whatever was pushed
+    fn into_error(
+        self,
+        context: impl FnOnce(String) -> String,
+        kind: fn(String) -> HarvestError,
+    ) -> HarvestError {
+        let message = context(self.message);
+        if self.auth {
+            HarvestError::AuthFailed(message)
+        } else {
+            kind(message)
+        }
+    }
+}
+
+/// Network hiccups are worth retrying, refused credentials, rejections and bad refs are not
+fn is_transient(err: &git2::Error) -> bool {
+    let permanent_code = matches!(
+        err.code(),
+        ErrorCode::Auth | ErrorCode::Certificate | ErrorCode::NotFound
+    );
+    let network_class = matches!(
+        err.class(),
+        ErrorClass::Net | ErrorClass::Os | ErrorClass::Ssh | ErrorClass::Http
+    );
+    network_class && !permanent_code
+}
+
+fn push_once(repo: &Repository, remote: &str, refspecs: &[&str]) -> Result<(), PushFailure> {
+    let mut remote = repo
+        .find_remote(remote)
+        .map_err(|_| PushFailure::permanent(format!("remote '{}' does not exist", remote)))?;
+    let config = repo
+        .config()
+        .map_err(|e| PushFailure::permanent(e.message().to_string()))?;
+    let mut missing = false;
+    let mut rejection = None;
+
+    let mut callbacks = auth::remote_callbacks(&config);
+    // Unlike git, libgit2 happily "deletes" a ref the remote doesn't have
+    callbacks.push_negotiation(|updates| {
+        if updates
+            .iter()
+            .any(|update| update.src().is_zero() && update.dst().is_zero())
+        {
+            missing = true;
+            return Err(git2::Error::from_str("remote ref does not exist"));
+        }
+        Ok(())
+    });
+    // The server reports rejected ref updates here rather than failing the push itself
+    callbacks.push_update_reference(|refname, status| {
+        if let (Some(status), None) = (status, &rejection) {
+            rejection = Some(match refspecs {
+                [_] => status.to_string(),
+                _ => format!("{}: {}", refname, status),
+            });
+        }
+        Ok(())
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    debug!(
+        "pushing refspec '{}' to remote '{}'",
+        refspecs.join(" "),
+        remote.name().unwrap_or("")
+    );
+    let result = remote.push(refspecs, Some(&mut options));
+    drop(options);
+
+    if missing {
+        return Err(PushFailure::permanent(
+            "remote ref does not exist".to_string(),
+        ));
+    }
+    result.map_err(|e| PushFailure {
+        message: auth::describe_error(&e),
+        transient: is_transient(&e),
+        auth: e.code() == ErrorCode::Auth,
+    })?;
+    match rejection {
+        Some(reason) => Err(PushFailure::permanent(reason)),
+        None => Ok(()),
+    }
+}
+
+/// Full ref of a branch on the remote. Names go into the refspec verbatim, no shell is
+/// involved, so slashes, dots, unicode and shell characters need no escaping; names git
+/// itself refuses are rejected before they can be read as refspec syntax
+fn branch_ref(branch_name: &str) -> Result<String, String> {
+    let reference = format!("refs/heads/{}", branch_name);
+    if !Reference::is_valid_name(&reference) {
+        return Err(format!("'{}' is not a valid branch name", branch_name));
+    }
+    Ok(reference)
+}
+
+fn delete_branch(
+    repo: &Repository,
+    remote: &str,
+    branch_name: String,
+    retries: u32,
+) -> Result<(), HarvestError> {
+    let context = |e: String| format!("failed to delete branch '{}': {}", branch_name, e);
+    let reference = branch_ref(&branch_name).map_err(|e| HarvestError::DeleteFailed(context(e)))?;
+    push_refspec(repo, remote, &format!(":{}", reference), retries)
+        .map_err(|failure| failure.into_error(context, HarvestError::DeleteFailed))
+}
+
+/// Name of the tag --archive keeps a deleted branch's commits reachable with
+fn archive_tag(branch: &BranchInfo) -> String {
+    format!("archive/{}", branch.name)
+}
+
+/// Tags the branch tip as archive/<branch>, and pushes the tag when the branch is remote
+fn archive_branch(
+    repo: &Repository,
+    remote: &str,
+    branch: &BranchInfo,
+    retries: u32,
+) -> Result<(), HarvestError> {
+    let tag = archive_tag(branch);
+    let reference = format!("refs/tags/{}", tag);
+    let failed = |reason: String| format!("failed to archive branch '{}': {}", branch.name, reason);
+    // A tag left by an earlier, interrupted cleanup of the same branch is reused
+    match repo.find_reference(&reference) {
+        Ok(existing) if existing.target() == Some(branch.tip) => {}
+        Ok(_) => return Err(failed(format!("tag '{}' already exists", tag)).into()),
+        Err(_) => {
+            debug!("tagging {} as '{}'", branch.tip, tag);
+            repo.reference(&reference, branch.tip, false, "git-harvest: archive")
+                .map_err(|e| failed(e.message().to_string()))?;
+        }
+    }
+    if branch.kind == BranchType::Remote {
+        let refspec = format!("{}:{}", reference, reference);
+        push_refspec(repo, remote, &refspec, retries)
+            .map_err(|failure| failure.into_error(failed, HarvestError::Other))?;
+    }
+    Ok(())
+}
+
+fn delete_local_branch(repo: &Repository, branch_name: &str) -> Result<(), HarvestError> {
+    debug!("deleting local branch '{}'", branch_name);
+    repo.find_branch(branch_name, BranchType::Local)
+        .and_then(|mut branch| branch.delete())
+        .map_err(|e| {
+            HarvestError::DeleteFailed(format!(
+                "failed to delete local branch '{}': {}",
+                branch_name,
+                e.message()
+            ))
+        })?;
+    Ok(())
+}
+
+/// What the stats group the branches by
+#[derive(Clone, Copy, PartialEq)]
+enum Grouping {
+    User,
+    Prefix,
+    Month,
+    Age,
+}
+
+impl Grouping {
+    fn of(args: &StatsArgs) -> Grouping {
+        if args.by_prefix {
+            Grouping::Prefix
+        } else if args.by_month {
+            Grouping::Month
+        } else if args.age_buckets {
+            Grouping::Age
+        } else {
+            Grouping::User
+        }
+    }
+
+    /// Names in --json output of the list of groups and of the key of each group
+    fn json_names(self) -> (&'static str, &'static str) {
+        match self {
+            Grouping::User => ("per_user", "email"),
+            Grouping::Prefix => ("per_prefix", "prefix"),
+            Grouping::Month => ("per_month", "month"),
+            Grouping::Age => ("per_age", "age"),
+        }
+    }
+
+    fn heading(self) -> &'static str {
+        match self {
+            Grouping::User => "Branches per user:",
+            Grouping::Prefix => "Branches per prefix:",
+            Grouping::Month => "Branches per month of their last commit:",
+            Grouping::Age => "Branches per age of their last commit:",
+        }
+    }
+}
+
+/// One group of branches, whichever grouping was asked for
+struct GroupStats {
+    key: String,
+    count: usize,
+    commits_ahead: Option<usize>,
+    commits: Option<usize>,
+    unique_tips: Option<usize>,
+}
+
+/// The groups of a report, written out as e.g. `"per_user": [{"email": ..., "count": ...}]`
+struct Groups {
+    grouping: Grouping,
+    list: Vec<GroupStats>,
+}
+
+impl Serialize for Groups {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        struct Group<'a>(&'static str, &'a GroupStats);
+        impl Serialize for Group<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let Group(key_name, group) = self;
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry(key_name, &group.key)?;
+                map.serialize_entry("count", &group.count)?;
+                for (name, value) in [
+                    ("commits_ahead", group.commits_ahead),
+                    ("commits", group.commits),
+                    ("unique_tips", group.unique_tips),
+                ] {
+                    if let Some(value) = value {
+                        map.serialize_entry(name, &value)?;
+                    }
+                }
+                map.end()
+            }
+        }
+
+        let (list_name, key_name) = self.grouping.json_names();
+        let list: Vec<Group> = self
+            .list
+            .iter()
+            .map(|group| Group(key_name, group))
+            .collect();
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(list_name, &list)?;
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct StatsReport {
+    total_branches: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distinct_authors: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branches_per_author: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unique_tips: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commits_ahead: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zero_ahead: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commits: Option<usize>,
+    #[serde(flatten)]
+    groups: Groups,
+    #[serde(skip)]
+    has_local: bool,
+}
 
-    (user_name, user_email)
+/// Drops local branches that have a remote counterpart, they are the same branch
+fn dedup_local(mut branches: Vec<BranchInfo>) -> Vec<BranchInfo> {
+    let remote_names: HashSet<String> = branches
+        .iter()
+        .filter(|branch| branch.kind == BranchType::Remote)
+        .map(|branch| branch.name.clone())
+        .collect();
+    branches
+        .retain(|branch| branch.kind == BranchType::Remote || !remote_names.contains(&branch.name));
+    branches
 }
 
-fn get_repo(path: String) -> Repository {
-    let repo = Repository::open(path).unwrap();
-    repo
+/// Number of branches per key, biggest groups first and ties broken alphabetically
+fn count_by<T>(branches: &[T], key: impl Fn(&T) -> String) -> Vec<(String, usize)> {
+    let mut branch_count = HashMap::new();
+    for branch in branches {
+        *branch_count.entry(key(branch)).or_insert(0) += 1;
+    }
+    let mut branch_count: Vec<(String, usize)> = branch_count.into_iter().collect();
+    branch_count.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    branch_count
+}
+
+/// An author's email as branches are grouped by: lowercased, as the --email filter matches it
+fn user_key(branch: &BranchInfo) -> String {
+    branch.author_email.to_lowercase()
+}
+
+fn count_per_user(branches: &[BranchInfo]) -> Vec<(String, usize)> {
+    count_by(branches, user_key)
+}
+
+/// Age buckets of stats --age-buckets, each holding branches up to that many days old
+const AGE_BUCKETS: [(&str, i64); 5] = [
+    ("0-7 days", 7),
+    ("8-30 days", 30),
+    ("31-90 days", 90),
+    ("91-365 days", 365),
+    (">365 days", i64::MAX),
+];
+
+fn age_bucket(age: i64) -> &'static str {
+    AGE_BUCKETS
+        .iter()
+        .find(|(_, days)| age <= *days)
+        .map_or(">365 days", |(label, _)| label)
+}
+
+/// The first path segment of a branch name, e.g. "feature/" for "feature/foo"
+fn branch_prefix(name: &str) -> String {
+    match name.split_once('/') {
+        Some((prefix, _)) => format!("{}/", prefix),
+        None => "<no-prefix>".to_string(),
+    }
 }
 
-fn get_remote_branches(repo: Repository) -> Vec<(String, String, String)> {
-    // List all remote branches
-    let branches = repo.branches(Some(BranchType::Remote)).unwrap();
+/// Groups the branches as asked by the stats flags. `ahead` holds each branch's count of
+/// commits ahead of its repository's default branch, when --show-ahead walked them, and
+/// `commits` those commits themselves, when --commits did
+fn stats_report(
+    branches: &[BranchInfo],
+    ahead: Option<&[usize]>,
+    commits: Option<&[Vec<Oid>]>,
+    args: &StatsArgs,
+) -> StatsReport {
+    let total_branches = branches.len();
+    let now = date::now();
+    let grouping = Grouping::of(args);
+    let key = |branch: &BranchInfo| match grouping {
+        Grouping::Prefix => branch_prefix(&branch.name),
+        Grouping::Month => date::format_month(&branch.time),
+        Grouping::Age => age_bucket(date::age_days(&branch.time, now)).to_string(),
+        Grouping::User => user_key(branch),
+    };
+    let mut branch_count = count_by(branches, key);
+
+    // Branches sharing a tip, e.g. release branches cut from one commit, count once
+    let mut tips_by_key: Option<HashMap<String, HashSet<Oid>>> = None;
+    if args.unique_tips {
+        let tips_by_key = tips_by_key.insert(HashMap::new());
+        for branch in branches {
+            tips_by_key
+                .entry(key(branch))
+                .or_default()
+                .insert(branch.tip);
+        }
+        branch_count.sort_by(|a, b| {
+            tips_by_key[&b.0]
+                .len()
+                .cmp(&tips_by_key[&a.0].len())
+                .then_with(|| b.1.cmp(&a.1))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+    }
+    let unique_tips_of = |key: &str| {
+        tips_by_key
+            .as_ref()
+            .map(|tips| tips.get(key).map_or(0, HashSet::len))
+    };
+    let total_unique_tips = args.unique_tips.then(|| {
+        branches
+            .iter()
+            .map(|branch| branch.tip)
+            .collect::<HashSet<_>>()
+            .len()
+    });
+    if grouping == Grouping::Month {
+        // Months read as a timeline, oldest first
+        branch_count.sort();
+    }
+    if grouping == Grouping::Age {
+        // Every bucket is shown, youngest first, so the empty ones stand out too
+        branch_count = AGE_BUCKETS
+            .iter()
+            .map(|(label, _)| {
+                let count = branch_count
+                    .iter()
+                    .find(|(key, _)| key == label)
+                    .map_or(0, |(_, count)| *count);
+                (label.to_string(), count)
+            })
+            .collect();
+    }
+
+    // Commits ahead of the default branch summed per group
+    let ahead_by_key = ahead.map(|ahead| {
+        let mut ahead_by_key: HashMap<String, usize> = HashMap::new();
+        for (branch, ahead) in branches.iter().zip(ahead) {
+            *ahead_by_key.entry(key(branch)).or_insert(0) += ahead;
+        }
+        ahead_by_key
+    });
+    let ahead_of = |key: &str| {
+        ahead_by_key
+            .as_ref()
+            .map(|ahead| ahead.get(key).copied().unwrap_or(0))
+    };
+
+    // Commits of the group's own, counted once however many of its branches have them
+    let commits_by_key = commits.map(|commits| {
+        let mut commits_by_key: HashMap<String, HashSet<Oid>> = HashMap::new();
+        for (branch, commits) in branches.iter().zip(commits) {
+            commits_by_key
+                .entry(key(branch))
+                .or_default()
+                .extend(commits);
+        }
+        commits_by_key
+    });
+    let commits_of = |key: &str| {
+        commits_by_key
+            .as_ref()
+            .map(|commits| commits.get(key).map_or(0, HashSet::len))
+    };
+
+    // How concentrated ownership is, whatever the branches are grouped by
+    let distinct_authors = branches.iter().map(user_key).collect::<HashSet<_>>().len();
+    let branches_per_author = if distinct_authors == 0 {
+        0.0
+    } else {
+        total_branches as f64 / distinct_authors as f64
+    };
+
+    StatsReport {
+        total_branches,
+        distinct_authors: Some(distinct_authors),
+        branches_per_author: Some(branches_per_author),
+        unique_tips: total_unique_tips,
+        commits_ahead: ahead.map(|ahead| ahead.iter().sum()),
+        zero_ahead: ahead.map(|ahead| ahead.iter().filter(|ahead| **ahead == 0).count()),
+        commits: commits.map(|commits| commits.iter().flatten().collect::<HashSet<_>>().len()),
+        groups: Groups {
+            grouping,
+            list: branch_count
+                .into_iter()
+                .map(|(key, count)| GroupStats {
+                    commits_ahead: ahead_of(&key),
+                    commits: commits_of(&key),
+                    unique_tips: unique_tips_of(&key),
+                    key,
+                    count,
+                })
+                .collect(),
+        },
+        has_local: branches
+            .iter()
+            .any(|branch| branch.kind == BranchType::Local),
+    }
+}
+
+fn print_stats(report: &StatsReport) {
+    let groups = &report.groups;
+    println!("{}", groups.grouping.heading());
+    let largest = groups
+        .list
+        .iter()
+        .map(|group| group.count)
+        .max()
+        .unwrap_or(0);
+    // Months and ages are a distribution, drawn as bars
+    let bars = matches!(groups.grouping, Grouping::Month | Grouping::Age);
+    for group in &groups.list {
+        let share = group.count as f64 * 100.0 / report.total_branches.max(1) as f64;
+        let mut line = format!(
+            "{}: {} ({:.1}%)",
+            group.key.cyan(),
+            group.count.to_string().bold(),
+            share
+        );
+        if let Some(unique_tips) = group.unique_tips {
+            line = format!("{}, {} unique tips", line, unique_tips);
+        }
+        if let Some(ahead) = group.commits_ahead {
+            line = format!("{}, {} commits ahead", line, ahead);
+        }
+        if let Some(commits) = group.commits {
+            line = format!("{}, {} commits", line, commits);
+        }
+        if bars && group.count > 0 {
+            // Scaled so the biggest group fills the bar
+            let width = (group.count * 40).div_ceil(largest.max(1));
+            line = format!("{}  {}", line, "#".repeat(width));
+        }
+        println!("{}", line);
+    }
+    let total_label = if report.has_local {
+        "Total Branches"
+    } else {
+        "Total Remote Branches"
+    };
+    println!(
+        "=========================\n {}: {}",
+        total_label, report.total_branches
+    );
+    if let Some(unique_tips) = report.unique_tips {
+        println!(" Unique Tips: {}", unique_tips);
+    }
+    if let Some(distinct_authors) = report.distinct_authors {
+        println!(" Distinct Authors: {}", distinct_authors);
+    }
+    if let Some(branches_per_author) = report.branches_per_author {
+        println!(" Branches per Author: {:.1}", branches_per_author);
+    }
+    if let (Some(total_ahead), Some(zero_ahead)) = (report.commits_ahead, report.zero_ahead) {
+        println!(" Commits ahead of the default branch: {}", total_ahead);
+        println!(" Branches with nothing ahead: {}", zero_ahead);
+    }
+    if let Some(commits) = report.commits {
+        println!(" Distinct commits ahead of the default branch: {}", commits);
+    }
+}
+
+#[derive(Serialize)]
+struct RepoStats {
+    location: String,
+    #[serde(flatten)]
+    stats: StatsReport,
+}
+
+#[derive(Serialize)]
+struct FleetReport {
+    repositories: Vec<RepoStats>,
+    total: StatsReport,
+}
+
+/// Stats of one repository, or of several summed up with --scan or a repeated --location
+fn stats(sources: &[Source], args: &StatsArgs) -> Result<(), HarvestError> {
+    if let (Some(since), Some(until)) = (args.since, args.until) {
+        if since > until {
+            return Err(HarvestError::InvalidFilter(
+                "--since is after --until, nothing can be in between".to_string(),
+            ));
+        }
+    }
+    check_age_window(args.older_than, args.newer_than)?;
+    // Both ends are inclusive, and compared with the date shown for the last commit
+    let now = date::now();
+    let in_range = |branch: &BranchInfo| {
+        let day = date::local_day(&branch.time);
+        let age = date::age_days(&branch.time, now);
+        args.since.is_none_or(|since| day >= since)
+            && args.until.is_none_or(|until| day <= until)
+            && in_age_window(age, args.older_than, args.newer_than)
+    };
+    let mut repositories = Vec::new();
+    for source in sources {
+        let repo = source.open()?;
+        let mut branches = dedup_local(source.branches(&repo)?);
+        branches.retain(|branch| in_range(branch));
+        // Only walked for when asked, each branch against its own repository's default branch
+        let ahead = if args.show_ahead {
+            let base = source.default_branch(&repo)?;
+            let ahead = branches
+                .iter()
+                .map(|branch| ahead_behind(&repo, branch.tip, base).map(|(ahead, _)| ahead))
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(ahead)
+        } else {
+            None
+        };
+        let commits = if args.commits {
+            let base = source.default_branch(&repo)?;
+            let progress = progress_bar("walking", branches.len() as u64);
+            let mut commits = Vec::new();
+            for branch in &branches {
+                progress.set_message(branch.name.clone());
+                commits.push(ahead_commits(&repo, branch.tip, base)?);
+                progress.inc(1);
+            }
+            progress.finish_and_clear();
+            Some(commits)
+        } else {
+            None
+        };
+        repositories.push((source.location.clone(), branches, ahead, commits));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        let locations: Vec<String> = repositories
+            .iter()
+            .map(|(location, ..)| location.clone())
+            .collect();
+        let branches: Vec<&BranchInfo> = repositories
+            .iter()
+            .flat_map(|(_, branches, ..)| branches)
+            .collect();
+        let per_author = count_by(&branches, |branch| user_key(branch));
+        history::record(path, now, &locations, branches.len(), &per_author)?;
+        debug!(
+            "recorded {} branches in '{}'",
+            branches.len(),
+            path.display()
+        );
+    }
+
+    if let [(_, branches, ahead, commits)] = repositories.as_slice() {
+        let report = stats_report(branches, ahead.as_deref(), commits.as_deref(), args);
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print_stats(&report);
+        }
+        return Ok(());
+    }
+
+    let mut all_branches = Vec::new();
+    let mut all_ahead = args.show_ahead.then(Vec::new);
+    let mut all_commits = args.commits.then(Vec::new);
+    let mut per_repo = Vec::new();
+    for (location, branches, ahead, commits) in repositories {
+        if args.per_repo {
+            per_repo.push(RepoStats {
+                stats: stats_report(&branches, ahead.as_deref(), commits.as_deref(), args),
+                location,
+            });
+        }
+        if let (Some(all_ahead), Some(ahead)) = (&mut all_ahead, ahead) {
+            all_ahead.extend(ahead);
+        }
+        if let (Some(all_commits), Some(commits)) = (&mut all_commits, commits) {
+            all_commits.extend(commits);
+        }
+        all_branches.extend(branches);
+    }
+    let total = stats_report(
+        &all_branches,
+        all_ahead.as_deref(),
+        all_commits.as_deref(),
+        args,
+    );
+    if args.json {
+        if args.per_repo {
+            let report = FleetReport {
+                repositories: per_repo,
+                total,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{}", serde_json::to_string_pretty(&total)?);
+        }
+        return Ok(());
+    }
+    for repo_stats in &per_repo {
+        println!("{}", format!("{}:", repo_stats.location).bold());
+        print_stats(&repo_stats.stats);
+        println!();
+    }
+    println!("{}", format!("All {} repositories:", sources.len()).bold());
+    print_stats(&total);
+    Ok(())
+}
+
+/// Branch names on the server at the URL, read without cloning, so without any commits
+fn ls_remote(url: &str) -> Result<Vec<String>, HarvestError> {
+    let config = Config::open_default()
+        .map_err(|e| format!("unable to read git configuration: {}", e.message()))?;
+    let mut remote = Remote::create_detached(url)
+        .map_err(|e| format!("invalid remote URL '{}': {}", url, e.message()))?;
+    list_remote_branches(&mut remote, &config, url)
+}
+
+/// Branch names the server currently has, asked over the network like git ls-remote
+fn list_remote_branches(
+    remote: &mut Remote,
+    config: &Config,
+    label: &str,
+) -> Result<Vec<String>, HarvestError> {
+    debug!("listing the branches of '{}'", label);
+    let connection = remote
+        .connect_auth(Direction::Fetch, Some(auth::remote_callbacks(config)), None)
+        .map_err(|e| error::remote_failure(format!("failed to connect to '{}'", label), &e))?;
+    let names = connection
+        .list()?
+        .iter()
+        .filter_map(|head| head.name().strip_prefix("refs/heads/"))
+        .map(str::to_string)
+        .collect();
+    Ok(names)
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum TagKind {
+    Annotated,
+    Lightweight,
+}
+
+struct TagInfo {
+    name: String,
+    kind: TagKind,
+    // The tagger of an annotated tag, the --by identity of the tagged commit otherwise
+    email: String,
+    time: Time,
+}
+
+/// Every tag of the repository that can be attributed to someone, through .mailmap
+fn get_tags(repo: &Repository, by: Identity) -> Result<Vec<TagInfo>, HarvestError> {
+    let mailmap = repo.mailmap().ok();
+    let mut tags = Vec::new();
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let Some(target) = repo
+            .find_reference(&format!("refs/tags/{}", name))
+            .ok()
+            .and_then(|reference| reference.target())
+        else {
+            continue;
+        };
+        if let Ok(tag) = repo.find_tag(target) {
+            if let Some(tagger) = tag.tagger() {
+                let tagger = match &mailmap {
+                    Some(mailmap) => mailmap
+                        .resolve_signature(&tagger)
+                        .unwrap_or_else(|_| tagger.to_owned()),
+                    None => tagger.to_owned(),
+                };
+                tags.push(TagInfo {
+                    name: name.to_string(),
+                    kind: TagKind::Annotated,
+                    email: tagger.email().unwrap_or("Unknown").to_string(),
+                    time: tagger.when(),
+                });
+                continue;
+            }
+        }
+        // Lightweight tags, and the rare annotated tag without a tagger, go by their commit
+        let kind = match repo.find_tag(target) {
+            Ok(_) => TagKind::Annotated,
+            Err(_) => TagKind::Lightweight,
+        };
+        let Ok(commit) = repo
+            .find_object(target, None)
+            .and_then(|object| object.peel_to_commit())
+        else {
+            debug!("{}: does not point at a commit, skipped", name);
+            continue;
+        };
+        let info = branch_info(
+            &commit,
+            mailmap.as_ref(),
+            String::new(),
+            BranchType::Local,
+            by,
+        );
+        tags.push(TagInfo {
+            name: name.to_string(),
+            kind,
+            email: info.author_email,
+            time: info.time,
+        });
+    }
+    Ok(tags)
+}
+
+#[derive(Serialize)]
+struct TagUserStats {
+    email: String,
+    count: usize,
+    annotated: usize,
+    lightweight: usize,
+}
+
+#[derive(Serialize)]
+struct TagEntry {
+    name: String,
+    kind: TagKind,
+    email: String,
+    date: String,
+    age: i64,
+}
+
+#[derive(Serialize)]
+struct TagsReport {
+    total_tags: usize,
+    annotated: usize,
+    lightweight: usize,
+    distinct_authors: usize,
+    per_user: Vec<TagUserStats>,
+    oldest: Vec<TagEntry>,
+}
+
+/// Stats over the tags, the branch stats' counterpart for tag sprawl
+fn tags(source: &Source, args: &TagsArgs) -> Result<(), HarvestError> {
+    let repo = source.open_repo()?;
+    let mut tags = get_tags(&repo, source.by)?;
+    let is_annotated = |tag: &&TagInfo| tag.kind == TagKind::Annotated;
+    let per_user: Vec<TagUserStats> = count_by(&tags, |tag| tag.email.clone())
+        .into_iter()
+        .map(|(email, count)| {
+            let annotated = tags
+                .iter()
+                .filter(|tag| tag.email == email)
+                .filter(is_annotated)
+                .count();
+            TagUserStats {
+                email,
+                count,
+                annotated,
+                lightweight: count - annotated,
+            }
+        })
+        .collect();
+    tags.sort_by_key(|tag| tag.time.seconds());
+    let now = date::now();
+    let total_annotated = tags.iter().filter(is_annotated).count();
+    let report = TagsReport {
+        total_tags: tags.len(),
+        annotated: total_annotated,
+        lightweight: tags.len() - total_annotated,
+        distinct_authors: per_user.len(),
+        per_user,
+        oldest: tags
+            .iter()
+            .take(args.oldest)
+            .map(|tag| TagEntry {
+                name: tag.name.clone(),
+                kind: tag.kind,
+                email: tag.email.clone(),
+                date: date::format_date(&tag.time),
+                age: date::age_days(&tag.time, now),
+            })
+            .collect(),
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Tags per user:");
+    for user in &report.per_user {
+        let share = user.count as f64 * 100.0 / report.total_tags.max(1) as f64;
+        println!(
+            "{}: {} ({:.1}%), {} annotated, {} lightweight",
+            user.email.cyan(),
+            user.count.to_string().bold(),
+            share,
+            user.annotated,
+            user.lightweight
+        );
+    }
+    if !report.oldest.is_empty() {
+        println!("\nOldest tags:");
+        let width = report
+            .oldest
+            .iter()
+            .map(|tag| tag.name.chars().count())
+            .max()
+            .unwrap_or(0);
+        for tag in &report.oldest {
+            let kind = match tag.kind {
+                TagKind::Annotated => "annotated",
+                TagKind::Lightweight => "lightweight",
+            };
+            println!(
+                "  {:<width$}  {}  {:<11}  {}",
+                tag.name,
+                tag.date.dimmed(),
+                kind,
+                tag.email.cyan()
+            );
+        }
+    }
+    println!(
+        "=========================\n Total Tags: {} ({} annotated, {} lightweight)",
+        report.total_tags, report.annotated, report.lightweight
+    );
+    println!(" Distinct Authors: {}", report.distinct_authors);
+    Ok(())
+}
+
+fn remote_stats(url: &str, args: &StatsArgs) -> Result<(), HarvestError> {
+    #[cfg(feature = "sqlite")]
+    if args.sqlite.is_some() {
+        return Err("--sqlite records per-author counts, which --remote-url can't give".into());
+    }
+    if !args.by_prefix
+        || args.show_ahead
+        || args.commits
+        || args.unique_tips
+        || args.since.is_some()
+        || args.until.is_some()
+        || args.older_than.is_some()
+        || args.newer_than.is_some()
+    {
+        return Err(
+            "--remote-url only counts branches per prefix, the commits aren't \
+                    fetched so there are no authors, dates or ahead counts, pass --by-prefix"
+                .into(),
+        );
+    }
+    let names = ls_remote(url)?;
+    let branch_count = count_by(&names, |name| branch_prefix(name));
+    let report = StatsReport {
+        total_branches: names.len(),
+        distinct_authors: None,
+        branches_per_author: None,
+        unique_tips: None,
+        commits_ahead: None,
+        zero_ahead: None,
+        commits: None,
+        groups: Groups {
+            grouping: Grouping::Prefix,
+            list: branch_count
+                .into_iter()
+                .map(|(key, count)| GroupStats {
+                    key,
+                    count,
+                    commits_ahead: None,
+                    commits: None,
+                    unique_tips: None,
+                })
+                .collect(),
+        },
+        has_local: false,
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_stats(&report);
+    }
+    Ok(())
+}
+
+fn remote_list(url: &str, args: &ListArgs) -> Result<(), HarvestError> {
+    let filter = &args.filter;
+    // Everything but the name needs the tip commits, which ls-remote does not download
+    let needs_commits = [
+        (filter.email.is_some(), "--email"),
+        (filter.committer_email.is_some(), "--committer-email"),
+        (!filter.exclude_email.is_empty(), "--exclude-email"),
+        (filter.name.is_some(), "--name"),
+        (filter.older_than.is_some(), "--older-than"),
+        (filter.newer_than.is_some(), "--newer-than"),
+        (args.limit.is_some(), "--limit"),
+        (args.sort.is_some(), "--sort"),
+        (args.reverse, "--reverse"),
+        (args.merged_into.is_some(), "--merged-into"),
+        (args.merged_only, "--merged-only"),
+        (args.no_merged, "--no-merged"),
+        (args.format.is_some(), "--format"),
+        (args.csv, "--csv"),
+        (args.json, "--json"),
+        (args.show_ahead, "--show-ahead"),
+        (args.show_created, "--show-created"),
+        (args.show_committer, "--show-committer"),
+        (args.show_churn, "--show-churn"),
+        (args.relative, "--relative"),
+        (args.forge.check_prs, "--check-prs"),
+    ];
+    let unsupported: Vec<&str> = needs_commits
+        .iter()
+        .filter(|(given, _)| *given)
+        .map(|(_, flag)| *flag)
+        .collect();
+    if !unsupported.is_empty() {
+        return Err(HarvestError::InvalidFilter(format!(
+            "--remote-url only sees branch names, it can't apply {}; of the list options only \
+             --pattern and --no-date work with it",
+            unsupported.join(", ")
+        )));
+    }
+    let pattern = match &filter.pattern {
+        Some(pattern) => Some(
+            Glob::new(pattern)
+                .map_err(|e| {
+                    HarvestError::InvalidFilter(format!("invalid --pattern '{}': {}", pattern, e))
+                })?
+                .compile_matcher(),
+        ),
+        None => None,
+    };
+    for name in ls_remote(url)? {
+        if pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&name))
+        {
+            println!("{}", name);
+        }
+    }
+    Ok(())
+}
+
+fn top(source: &Source, n: usize) -> Result<(), HarvestError> {
+    let repo = source.open()?;
+    let branches = dedup_local(source.branches(&repo)?);
+    for (rank, (email, count)) in count_per_user(&branches).into_iter().take(n).enumerate() {
+        println!(
+            "{:>3}. {}: {}",
+            rank + 1,
+            email.cyan(),
+            count.to_string().bold()
+        );
+    }
+    Ok(())
+}
+
+/// Lowercased emails of everyone with a commit on the branch that the base does not have,
+/// mapped through .mailmap, or the tip's identity when the branch has nothing of its own
+fn branch_contributors(
+    repo: &Repository,
+    mailmap: Option<&Mailmap>,
+    branch: &BranchInfo,
+    base: Oid,
+    by: Identity,
+) -> Result<HashSet<String>, HarvestError> {
+    let mut walk = repo.revwalk()?;
+    walk.push(branch.tip)?;
+    walk.hide(base)?;
+    let mut emails = HashSet::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let info = branch_info(&commit, mailmap, String::new(), branch.kind, by);
+        emails.insert(info.author_email.to_lowercase());
+    }
+    if emails.is_empty() {
+        emails.insert(branch.author_email.to_lowercase());
+    }
+    Ok(emails)
+}
+
+#[derive(Serialize)]
+struct CompareSide {
+    email: String,
+    branches: usize,
+    exclusive: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CompareReport {
+    a: CompareSide,
+    b: CompareSide,
+    shared: Vec<String>,
+}
+
+/// Splits the branches two people worked on into the ones only one of them touched and the
+/// ones they share, judged by the commits each branch has over the default branch
+fn compare(source: &Source, args: &CompareArgs) -> Result<(), HarvestError> {
+    let repo = source.open()?;
+    let base = source.default_branch(&repo)?;
+    let mailmap = repo.mailmap().ok();
+    let mut only_a = Vec::new();
+    let mut only_b = Vec::new();
+    let mut shared = Vec::new();
+    for branch in dedup_local(source.branches(&repo)?) {
+        let emails = branch_contributors(&repo, mailmap.as_ref(), &branch, base, source.by)?;
+        let has_a = emails
+            .iter()
+            .any(|email| email_matches(&args.a, email, false));
+        let has_b = emails
+            .iter()
+            .any(|email| email_matches(&args.b, email, false));
+        match (has_a, has_b) {
+            (true, true) => shared.push(branch.display_name()),
+            (true, false) => only_a.push(branch.display_name()),
+            (false, true) => only_b.push(branch.display_name()),
+            (false, false) => {}
+        }
+    }
+    for names in [&mut only_a, &mut only_b, &mut shared] {
+        names.sort();
+    }
+    let report = CompareReport {
+        a: CompareSide {
+            email: args.a.clone(),
+            branches: only_a.len() + shared.len(),
+            exclusive: only_a,
+        },
+        b: CompareSide {
+            email: args.b.clone(),
+            branches: only_b.len() + shared.len(),
+            exclusive: only_b,
+        },
+        shared,
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let width = report.a.email.chars().count();
+    println!(
+        "{}",
+        format!("{:<10}  {:<width$}  {}", "", report.a.email, report.b.email).bold()
+    );
+    println!(
+        "{:<10}  {:<width$}  {}",
+        "Branches", report.a.branches, report.b.branches
+    );
+    println!(
+        "{:<10}  {:<width$}  {}",
+        "Exclusive",
+        report.a.exclusive.len(),
+        report.b.exclusive.len()
+    );
+    println!("{:<10}  {}", "Shared", report.shared.len());
+    for (title, names) in [
+        (format!("Only {}", report.a.email), &report.a.exclusive),
+        (format!("Only {}", report.b.email), &report.b.exclusive),
+        ("Shared".to_string(), &report.shared),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        println!("\n{}:", title.bold());
+        for name in names {
+            println!("  {}", name);
+        }
+    }
+    Ok(())
+}
+
+/// Commit a branch spec such as "main" or "origin/main" points at, falling back to the
+/// remote's branch of that name when there is no local one
+fn resolve_commit(repo: &Repository, remote: &str, spec: &str) -> Result<Oid, HarvestError> {
+    let commit = repo
+        .revparse_single(spec)
+        .or_else(|_| repo.revparse_single(&format!("refs/remotes/{}/{}", remote, spec)))
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|_| {
+            HarvestError::BranchNotFound(format!("unable to resolve '{}' to a commit", spec))
+        })?;
+    Ok(commit.id())
+}
+
+/// Numbers of commits on the branch that the base does not have, and of commits on the base
+/// the branch does not have, costs a walk of the history
+fn ahead_behind(repo: &Repository, tip: Oid, base: Oid) -> Result<(usize, usize), HarvestError> {
+    repo.graph_ahead_behind(tip, base).map_err(|e| {
+        format!(
+            "unable to compare {} with the default branch: {}",
+            tip,
+            e.message()
+        )
+        .into()
+    })
+}
+
+/// The commits on the branch that the base does not have, costs a walk of the history
+fn ahead_commits(repo: &Repository, tip: Oid, base: Oid) -> Result<Vec<Oid>, HarvestError> {
+    let mut walk = repo.revwalk()?;
+    walk.push(tip)?;
+    walk.hide(base)?;
+    Ok(walk.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Lines a branch adds and removes
+#[derive(Clone, Copy, Serialize)]
+struct Churn {
+    added: usize,
+    removed: usize,
+}
+
+/// What the branch changes since it forked from the base, diffing its tip against their merge
+/// base; a branch sharing no history with the base is diffed against nothing
+fn branch_churn(repo: &Repository, tip: Oid, base: Oid) -> Result<Churn, HarvestError> {
+    let failed = |e: git2::Error| format!("unable to diff {}: {}", tip, e.message());
+    let fork_tree = match repo.merge_base(tip, base) {
+        Ok(fork) => Some(
+            repo.find_commit(fork)
+                .and_then(|fork| fork.tree())
+                .map_err(failed)?,
+        ),
+        Err(_) => None,
+    };
+    let tip_tree = repo
+        .find_commit(tip)
+        .and_then(|tip| tip.tree())
+        .map_err(failed)?;
+    let stats = repo
+        .diff_tree_to_tree(fork_tree.as_ref(), Some(&tip_tree), None)
+        .and_then(|diff| diff.stats())
+        .map_err(failed)?;
+    Ok(Churn {
+        added: stats.insertions(),
+        removed: stats.deletions(),
+    })
+}
+
+/// When the branch was started: the date of its oldest commit the base does not have, None
+/// when it has nothing of its own. Costs a walk of the history like commits_ahead.
+fn created_time(repo: &Repository, tip: Oid, base: Oid) -> Result<Option<Time>, HarvestError> {
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    walk.push(tip)?;
+    walk.hide(base)?;
+    match walk.next() {
+        Some(oid) => Ok(Some(repo.find_commit(oid?)?.time())),
+        None => Ok(None),
+    }
+}
+
+/// Whether the branch tip is reachable from the target, i.e. already merged into it
+fn is_merged(repo: &Repository, tip: Oid, target: Oid) -> bool {
+    tip == target || repo.graph_descendant_of(target, tip).unwrap_or(false)
+}
+
+/// Pushes a deleted branch back to the remote, from its archive tag or the given commit
+fn restore(source: &Source, branch: &str, commit: Option<&str>) -> Result<(), HarvestError> {
+    let destination = branch_ref(branch)?;
+    let repo = source.open_repo()?;
+    let tracking = format!("refs/remotes/{}/{}", source.remote, branch);
+    if repo.find_reference(&tracking).is_ok() {
+        return Err(format!(
+            "branch '{}' already exists on remote '{}'",
+            branch, source.remote
+        )
+        .into());
+    }
+    let tip = match commit {
+        Some(spec) => repo
+            .revparse_single(spec)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| {
+                HarvestError::BranchNotFound(format!("unable to resolve '{}' to a commit", spec))
+            })?
+            .id(),
+        None => {
+            let tag = format!("refs/tags/archive/{}", branch);
+            repo.find_reference(&tag)
+                .and_then(|reference| reference.peel_to_commit())
+                .map_err(|_| {
+                    HarvestError::BranchNotFound(format!(
+                        "no archive/{} tag to restore from, fetch the tags with `git fetch {} \
+                         --tags` or pass --commit",
+                        branch, source.remote
+                    ))
+                })?
+                .id()
+        }
+    };
+    // Pushing needs a ref as the source, a commit is pushed through a temporary one
+    let staging = "refs/git-harvest/restore";
+    repo.reference(staging, tip, true, "git-harvest: restore")?;
+    let refspec = format!("{}:{}", staging, destination);
+    let result = push_refspec(&repo, &source.remote, &refspec, 0);
+    repo.find_reference(staging)?.delete()?;
+    result.map_err(|failure| {
+        failure.into_error(
+            |e| format!("failed to restore branch '{}': {}", branch, e),
+            HarvestError::Other,
+        )
+    })?;
+    inform!(
+        "restored {} on remote '{}' at {}",
+        branch,
+        source.remote,
+        tip
+    );
+    Ok(())
+}
+
+/// Renames a remote branch, pushing its tip under the new name and deleting the old name in
+/// the same push
+fn rename(source: &Source, protected: &GlobSet, args: &RenameArgs) -> Result<(), HarvestError> {
+    let failed = |reason: String| format!("failed to rename '{}': {}", args.old, reason);
+    let old_ref = branch_ref(&args.old).map_err(failed)?;
+    let new_ref = branch_ref(&args.new).map_err(failed)?;
+    if protected.is_match(&args.old) {
+        return Err(failed("the branch is protected".to_string()).into());
+    }
+    if !args.dry_run && !args.yes && !std::io::stdin().is_terminal() {
+        return Err("stdin is not a terminal, pass --yes to rename without prompting".into());
+    }
+    let repo = source.open_repo()?;
+    let tracking = |name: &str| format!("refs/remotes/{}/{}", source.remote, name);
+    let old_tracking = tracking(&args.old);
+    let tip = repo
+        .find_reference(&old_tracking)
+        .ok()
+        .and_then(|reference| reference.target())
+        .ok_or_else(|| {
+            HarvestError::BranchNotFound(failed(format!(
+                "remote '{}' has no such branch, fetch it first",
+                source.remote
+            )))
+        })?;
+    if repo.find_reference(&tracking(&args.new)).is_ok() {
+        return Err(failed(format!("branch '{}' already exists", args.new)).into());
+    }
+    if args.dry_run {
+        println!(
+            "would rename {} to {} on remote '{}', at {}",
+            args.old, args.new, source.remote, tip
+        );
+        return Ok(());
+    }
+    if !args.yes
+        && !Confirm::new()
+            .with_prompt(format!(
+                "Rename '{}' to '{}' on remote '{}', deleting '{}'?",
+                args.old, args.new, source.remote, args.old
+            ))
+            .interact()?
+    {
+        inform!("rename cancelled");
+        return Ok(());
+    }
+    let create = format!("{}:{}", old_tracking, new_ref);
+    let delete = format!(":{}", old_ref);
+    push_refspecs(&repo, &source.remote, &[&create, &delete], args.retries)
+        .map_err(|failure| failure.into_error(failed, HarvestError::Other))?;
+    inform!(
+        "renamed {} to {} on remote '{}'",
+        args.old,
+        args.new,
+        source.remote
+    );
+    Ok(())
+}
+
+/// Moves a remote branch to a new name: its tip is pushed under the new name, then the old
+/// name is deleted
+fn adopt_branch(
+    repo: &Repository,
+    remote: &str,
+    branch: &BranchInfo,
+    new_name: &str,
+    retries: u32,
+) -> Result<(), HarvestError> {
+    let failed = |reason: String| format!("failed to adopt branch '{}': {}", branch.name, reason);
+    let destination = branch_ref(new_name).map_err(failed)?;
+    if repo
+        .find_reference(&format!("refs/remotes/{}/{}", remote, new_name))
+        .is_ok()
+    {
+        return Err(failed(format!("branch '{}' already exists", new_name)).into());
+    }
+    let source = format!("refs/remotes/{}/{}", remote, branch.name);
+    push_refspec(
+        repo,
+        remote,
+        &format!("{}:{}", source, destination),
+        retries,
+    )
+    .map_err(|failure| failure.into_error(failed, HarvestError::Other))?;
+    delete_branch(repo, remote, branch.name.clone(), retries)
+}
+
+/// Name a departing author's branch gets when adopted into the namespace, without their
+/// own namespace if the branch had one
+fn adopted_name(branch: &str, from: &str, namespace: &str) -> String {
+    let from_namespace = format!("{}/", from.split('@').next().unwrap_or(from));
+    let rest = branch
+        .get(..from_namespace.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(&from_namespace))
+        .map_or(branch, |_| &branch[from_namespace.len()..]);
+    format!("{}/{}", namespace, rest)
+}
+
+/// Walks through every remote branch of a departing author, asking whether to delete,
+/// archive, adopt or keep each one, then sums up what happened
+fn offboard(source: &Source, protected: &GlobSet, args: &OffboardArgs) -> Result<(), HarvestError> {
+    if !args.dry_run && !std::io::stdin().is_terminal() {
+        return Err("offboard asks what to do with each branch, stdin must be a terminal".into());
+    }
+    let repo = source.open_repo()?;
+    if let Some(problem) = source.remote_problem(&repo) {
+        return Err(HarvestError::NoRemote(format!(
+            "nothing to offboard, {}",
+            problem
+        )));
+    }
+    let mut branches: Vec<BranchInfo> = source
+        .branches(&repo)?
+        .into_iter()
+        .filter(|branch| branch.kind == BranchType::Remote)
+        .filter(|branch| email_matches(&args.from, &branch.author_email, false))
+        .collect();
+    branches.sort_by(|a, b| a.name.cmp(&b.name));
+    if branches.is_empty() {
+        inform!(
+            "{} has no branches on remote '{}'",
+            args.from,
+            source.remote
+        );
+        return Ok(());
+    }
+    if args.dry_run {
+        for branch in &branches {
+            println!(
+                "{}  {}",
+                branch.display_name(),
+                date::format_date(&branch.time)
+            );
+        }
+        inform!("{} branches by {}", branches.len(), args.from);
+        return Ok(());
+    }
+
+    // Adopted branches move under the namespace, by default the local part of user.email
+    let namespace = match &args.namespace {
+        Some(namespace) => Some(namespace.clone()),
+        None => get_current_user_info(&source.location)?
+            .1
+            .and_then(|email| email.split('@').next().map(str::to_lowercase)),
+    };
+    let mut actions = vec!["keep", "delete", "archive and delete"];
+    if namespace.is_some() {
+        actions.push("adopt");
+    }
+    let mut summary = Vec::new();
+    for branch in &branches {
+        if protected.is_match(&branch.name) {
+            summary.push((branch.display_name(), "kept (protected)".to_string()));
+            continue;
+        }
+        let adopted = namespace
+            .as_ref()
+            .map(|namespace| adopted_name(&branch.name, &args.from, namespace));
+        let choice = Select::new()
+            .with_prompt(format!(
+                "{} ({}, last commit {})",
+                branch.display_name(),
+                branch.author_email,
+                date::format_date(&branch.time)
+            ))
+            .items(&actions)
+            .default(0)
+            .interact()?;
+        let result = match actions[choice] {
+            "delete" => delete(&repo, &source.remote, branch, false, args.retries)
+                .map(|()| "deleted".to_string()),
+            "archive and delete" => delete(&repo, &source.remote, branch, true, args.retries)
+                .map(|()| format!("deleted, archived as {}", archive_tag(branch))),
+            "adopt" => {
+                let adopted = adopted.expect("adopt is only offered with a namespace");
+                adopt_branch(&repo, &source.remote, branch, &adopted, args.retries)
+                    .map(|()| format!("adopted as {}", adopted))
+            }
+            _ => Ok("kept".to_string()),
+        };
+        let outcome = match result {
+            Ok(outcome) => {
+                inform!("{}: {}", branch.display_name(), outcome);
+                outcome
+            }
+            Err(err) => {
+                eprintln!("{}", err.to_string().red());
+                format!("failed: {}", err)
+            }
+        };
+        summary.push((branch.display_name(), outcome));
+    }
+
+    let width = summary
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    println!("\n{}", format!("Offboarded {}:", args.from).bold());
+    for (name, outcome) in &summary {
+        let outcome = if outcome.starts_with("failed") {
+            outcome.red()
+        } else {
+            outcome.normal()
+        };
+        println!("  {:<width$}  {}", name, outcome);
+    }
+    let failed = summary
+        .iter()
+        .filter(|(_, outcome)| outcome.starts_with("failed"))
+        .count();
+    if failed > 0 {
+        return Err(format!("{} branches could not be offboarded", failed).into());
+    }
+    Ok(())
+}
+
+fn delete(
+    repo: &Repository,
+    remote: &str,
+    branch: &BranchInfo,
+    archive: bool,
+    retries: u32,
+) -> Result<(), HarvestError> {
+    if archive {
+        archive_branch(repo, remote, branch, retries)?;
+    }
+    match branch.kind {
+        BranchType::Remote => delete_branch(repo, remote, branch.name.clone(), retries),
+        BranchType::Local => delete_local_branch(repo, &branch.name),
+    }
+}
+
+/// Progress through `len` branches, e.g. "deleting 3/10", drawn on stderr only when it is a
+/// terminal
+fn progress_bar(verb: &str, len: u64) -> ProgressBar {
+    if quiet() || !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let progress = ProgressBar::new(len);
+    progress.set_style(
+        ProgressStyle::with_template(&format!("{} {{pos}}/{{len}} {{bar:30}} {{msg}}", verb))
+            .expect("valid progress template"),
+    );
+    progress
+}
+
+/// One line of the `cleanup --log` file
+#[derive(Serialize)]
+struct DeletionRecord<'a> {
+    timestamp: String,
+    remote: Option<&'a str>,
+    branch: &'a str,
+    email: &'a str,
+    tip: String,
+    status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archived_as: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+fn log_deletion(
+    log: &mut std::fs::File,
+    source: &Source,
+    branch: &BranchInfo,
+    archived_as: Option<String>,
+    error: Option<&str>,
+) -> std::io::Result<()> {
+    let record = DeletionRecord {
+        timestamp: date::format_timestamp(date::now()),
+        remote: (branch.kind == BranchType::Remote).then_some(source.remote.as_str()),
+        branch: &branch.name,
+        email: &branch.author_email,
+        tip: branch.tip.to_string(),
+        status: if error.is_some() { "failed" } else { "deleted" },
+        archived_as,
+        error,
+    };
+    writeln!(log, "{}", serde_json::to_string(&record)?)
+}
+
+/// Labels such as "open PR #12" of the remote branches that still have an open pull or merge
+/// request, None when --check-prs is off or the forge could not be asked
+fn open_requests(
+    repo: &Repository,
+    source: &Source,
+    args: &ForgeArgs,
+) -> Option<HashMap<String, String>> {
+    if !args.check_prs {
+        return None;
+    }
+    let note = |message: String| {
+        if !quiet() {
+            eprintln!("{}", format!("note: {}", message).yellow());
+        }
+    };
+    let url = repo
+        .find_remote(&source.remote)
+        .ok()
+        .and_then(|remote| remote.url().map(str::to_string));
+    let project = url
+        .as_deref()
+        .and_then(|url| forge::Project::from_url(url, &args.gitlab_host));
+    let Some(project) = project else {
+        note(format!(
+            "remote '{}' is not on GitHub or GitLab, open requests are not checked, pass \
+             --gitlab-host for a self-hosted GitLab",
+            source.remote
+        ));
+        return None;
+    };
+    let token = if project.is_github() {
+        args.github_token
+            .clone()
+            .or_else(|| std::env::var("GITHUB_TOKEN").ok())
+    } else {
+        args.gitlab_token
+            .clone()
+            .or_else(|| std::env::var("GITLAB_TOKEN").ok())
+    };
+    match project.open_requests(token.as_deref()) {
+        Ok(requests) => Some(
+            requests
+                .into_iter()
+                .map(|(branch, number)| (branch, format!("open {}", project.label(number))))
+                .collect(),
+        ),
+        Err(err) => {
+            note(format!(
+                "unable to check open requests, going on without: {}{}",
+                err,
+                if token.is_none() {
+                    " (no token given)"
+                } else {
+                    ""
+                }
+            ));
+            None
+        }
+    }
+}
+
+/// The branch checked out in the working tree and, when it tracks a branch of `remote`,
+/// that branch's name on the remote. Falls back to the same name when no upstream is set.
+fn checked_out_branch(repo: &Repository, remote: &str) -> Option<(String, String)> {
+    if repo.is_bare() {
+        return None;
+    }
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    let local = head.shorthand()?.to_string();
+    let tracking_prefix = format!("refs/remotes/{}/", remote);
+    let upstream = git2::Branch::wrap(head)
+        .upstream()
+        .ok()
+        .and_then(|upstream| upstream.get().name().map(str::to_string))
+        .and_then(|name| name.strip_prefix(&tracking_prefix).map(str::to_string))
+        .unwrap_or_else(|| local.clone());
+    Some((local, upstream))
+}
+
+#[derive(Serialize)]
+struct CleanupPlanEntry {
+    name: String,
+    email: String,
+    age_days: i64,
+    /// None when there is no default branch to tell it against
+    merged: Option<bool>,
+    protected: bool,
+    would_delete: bool,
+}
+
+/// Opens the terminal UI on the matching branches, deleting the ones picked in it
+#[cfg(feature = "tui")]
+fn browse(
+    source: &Source,
+    filter: &BranchFilter,
+    protected: &GlobSet,
+    args: &BrowseArgs,
+) -> Result<(), HarvestError> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err("browse is interactive, it needs a terminal".into());
+    }
+    let kept = match &args.keep_file {
+        Some(path) => load_keep_file(path)?,
+        None => HashSet::new(),
+    };
+    let repo = source.open_repo()?;
+    if let Some(problem) = source.remote_problem(&repo) {
+        return Err(HarvestError::NoRemote(format!(
+            "nothing to browse, {}",
+            problem
+        )));
+    }
+    let base = source.default_branch(&repo)?;
+    // The checked-out branch and its upstream can't be deleted from here, as in cleanup
+    let checked_out = checked_out_branch(&repo, &source.remote);
+    let is_current = |branch: &BranchInfo| {
+        checked_out
+            .as_ref()
+            .is_some_and(|(local, upstream)| match branch.kind {
+                BranchType::Local => &branch.name == local,
+                BranchType::Remote => &branch.name == upstream,
+            })
+    };
+    let now = date::now();
+    let entries = source
+        .branches(&repo)?
+        .into_iter()
+        .filter(|branch| filter.matches(branch))
+        .map(|branch| browse::Entry {
+            age: date::age_days(&branch.time, now),
+            merged: is_merged(&repo, branch.tip, base),
+            protected: protected.is_match(&branch.name)
+                || kept.contains(&branch.name)
+                || is_current(&branch),
+            branch,
+        })
+        .collect();
+    let deleted = browse::run(entries, args.force, |branch| {
+        delete(&repo, &source.remote, branch, false, args.retries).map_err(|e| e.to_string())
+    })?;
+    inform!("deleted {} branches", deleted);
+    Ok(())
+}
+
+/// Number of branches above which cleanup --yes asks to confirm the count, unless the config
+/// file sets bulk_threshold
+const BULK_THRESHOLD: usize = 20;
+
+/// Has the count of branches, or DELETE, typed in before a --yes cleanup deletes more of them
+/// than the threshold, so a filter scoped wrong doesn't go through unnoticed
+fn confirm_bulk(count: usize, threshold: usize) -> Result<(), HarvestError> {
+    let problem = format!(
+        "cleanup would delete {} branches, more than the bulk threshold of {}",
+        count, threshold
+    );
+    if !std::io::stdin().is_terminal() {
+        return Err(format!("{}, pass --force-bulk to go ahead", problem).into());
+    }
+    eprintln!("{}", format!("warning: {}", problem).red().bold());
+    let answer: String = Input::new()
+        .with_prompt(format!("Type {} or DELETE to proceed", count))
+        .allow_empty(true)
+        .interact_text()?;
+    let answer = answer.trim();
+    if answer == "DELETE" || answer == count.to_string() {
+        Ok(())
+    } else {
+        Err("cleanup aborted, nothing was deleted".into())
+    }
+}
+
+fn cleanup(
+    source: &Source,
+    filter: &BranchFilter,
+    protected: &GlobSet,
+    args: &CleanupArgs,
+    bulk_threshold: usize,
+) -> Result<(), HarvestError> {
+    let (dry_run, yes) = (args.dry_run, args.yes);
+    if args.max_parallel > 1 && !yes && !dry_run {
+        return Err(
+            "--max-parallel needs --yes, the prompts ask about one branch at a time".into(),
+        );
+    }
+    let kept = match &args.keep_file {
+        Some(path) => load_keep_file(path)?,
+        None => HashSet::new(),
+    };
+    if !dry_run && !yes && !std::io::stdin().is_terminal() {
+        return Err("stdin is not a terminal, pass --yes to delete without prompting".into());
+    }
+    let repo = source.open_repo()?;
+    if let Some(problem) = source.remote_problem(&repo) {
+        return Err(HarvestError::NoRemote(format!(
+            "nothing to clean up, {}",
+            problem
+        )));
+    }
+    if uses_bare_heads(&repo, &source.remote) && repo.find_remote(&source.remote).is_ok() {
+        return Err(format!(
+            "'{}' is a bare copy of remote '{}', deleting its branches would not remove them \
+             from the remote, run cleanup from a clone instead",
+            source.location, source.remote
+        )
+        .into());
+    }
+    // Unless forced, only branches whose work already landed are deleted
+    let target = if args.force {
+        None
+    } else {
+        Some(source.default_branch(&repo)?)
+    };
+    let zero_ahead_base = match target {
+        _ if !args.only_zero_ahead => None,
+        Some(target) => Some(target),
+        None => Some(source.default_branch(&repo)?),
+    };
+
+    let checked_out = checked_out_branch(&repo, &source.remote);
+    let requests = open_requests(&repo, source, &args.forge);
+    // The plan printed by --json, where merged is told even when --force skips the check
+    let merge_target = match target {
+        None if args.json => source.default_branch(&repo).ok(),
+        _ => target,
+    };
+    let now = date::now();
+    let mut plan = Vec::new();
+    let mut plan_entry = |branch: &BranchInfo, protected: bool, would_delete: bool| {
+        if args.json {
+            plan.push(CleanupPlanEntry {
+                name: branch.display_name(),
+                email: branch.author_email.clone(),
+                age_days: date::age_days(&branch.time, now),
+                merged: merge_target.map(|target| is_merged(&repo, branch.tip, target)),
+                protected,
+                would_delete,
+            });
+        }
+    };
+    // Skips are explained in the text output only, --json keeps stdout parseable
+    let skip = |message: String| {
+        if !args.json {
+            inform!("{}", message.yellow());
+        }
+    };
+    let mut skipped = 0;
+    let mut candidates = Vec::new();
+    for branch in source.branches(&repo)? {
+        if !filter.matches(&branch) {
+            continue;
+        }
+        if kept.contains(&branch.name) {
+            skip(format!("kept (allowlist) {}", branch.display_name()));
+            plan_entry(&branch, true, false);
+            skipped += 1;
+            continue;
+        }
+        if protected.is_match(&branch.name) {
+            skip(format!(
+                "skipping protected branch {}",
+                branch.display_name()
+            ));
+            plan_entry(&branch, true, false);
+            skipped += 1;
+            continue;
+        }
+        let request = requests
+            .as_ref()
+            .filter(|_| branch.kind == BranchType::Remote)
+            .and_then(|requests| requests.get(&branch.name));
+        if let Some(request) = request {
+            if !args.force {
+                skip(format!(
+                    "skipping {}, {} (use --force)",
+                    branch.display_name(),
+                    request
+                ));
+                plan_entry(&branch, false, false);
+                skipped += 1;
+                continue;
+            }
+            eprintln!(
+                "{}",
+                format!(
+                    "warning: deleting {} despite its {}",
+                    branch.display_name(),
+                    request
+                )
+                .red()
+            );
+        }
+        // Removing the branch you are sitting on, or its upstream, leaves a confusing state
+        let current = checked_out
+            .as_ref()
+            .filter(|(local, upstream)| match branch.kind {
+                BranchType::Local => &branch.name == local,
+                BranchType::Remote => &branch.name == upstream,
+            })
+            .map(|(local, _)| local);
+        if let Some(local) = current {
+            if !args.allow_current {
+                skip(format!(
+                    "skipping {}, '{}' is checked out here (use --allow-current)",
+                    branch.display_name(),
+                    local
+                ));
+                plan_entry(&branch, false, false);
+                skipped += 1;
+                continue;
+            }
+            eprintln!(
+                "{}",
+                format!(
+                    "warning: {} will be deleted while '{}' is checked out here",
+                    branch.display_name(),
+                    local
+                )
+                .red()
+                .bold()
+            );
+        }
+        if let Some(base) = zero_ahead_base {
+            let (ahead, _) = ahead_behind(&repo, branch.tip, base)?;
+            if ahead > 0 {
+                skip(format!(
+                    "skipping {}, {} ahead of the default branch",
+                    branch.display_name(),
+                    ahead
+                ));
+                plan_entry(&branch, false, false);
+                skipped += 1;
+                continue;
+            }
+        }
+        if let Some(target) = target {
+            if !is_merged(&repo, branch.tip, target) {
+                skip(format!(
+                    "skipping unmerged branch {} (use --force)",
+                    branch.display_name()
+                ));
+                plan_entry(&branch, false, false);
+                skipped += 1;
+                continue;
+            }
+        }
+        plan_entry(&branch, false, true);
+        candidates.push(branch);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+        return Ok(());
+    }
+    if dry_run {
+        for branch in &candidates {
+            println!("would delete: {}", branch.display_name());
+        }
+        inform!(
+            "{} branches would be deleted, {} skipped",
+            candidates.len(),
+            skipped
+        );
+        return Ok(());
+    }
+
+    if yes && candidates.len() > bulk_threshold && !args.force_bulk {
+        confirm_bulk(candidates.len(), bulk_threshold)?;
+    }
+
+    // Opened before anything is deleted, so an unwritable log stops the cleanup early
+    let mut log = match &args.log {
+        Some(path) => Some(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("cannot open log file '{}': {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    let mut deleted = 0;
+    let mut failures = Vec::new();
+    let attempt = |repo: &Repository, branch: &BranchInfo| {
+        delete(repo, &source.remote, branch, args.archive, args.retries).map_err(|e| e.to_string())
+    };
+    // A failed delete is reported and the remaining branches are still processed
+    let mut report = |branch: &BranchInfo, result: Result<(), String>| {
+        match &result {
+            Ok(()) if args.archive => {
+                inform!(
+                    "deleted {}, archived as {}",
+                    branch.display_name(),
+                    archive_tag(branch)
+                );
+                deleted += 1;
+            }
+            Ok(()) => {
+                inform!("deleted {}", branch.display_name());
+                deleted += 1;
+            }
+            Err(err) => {
+                eprintln!("{}", err.red());
+                failures.push(err.clone());
+            }
+        }
+        if let Some(log) = log.as_mut() {
+            let archived_as = (args.archive && result.is_ok()).then(|| archive_tag(branch));
+            let error = result.as_ref().err().map(String::as_str);
+            if let Err(err) = log_deletion(log, source, branch, archived_as, error) {
+                let message = format!("could not log {}: {}", branch.display_name(), err);
+                eprintln!("{}", message.red());
+                failures.push(message);
+            }
+        }
+    };
+    if args.select {
+        let items: Vec<String> = candidates
+            .iter()
+            .map(|branch| {
+                format!(
+                    "{}  {}",
+                    branch.display_name(),
+                    date::format_date(&branch.time)
+                )
+            })
+            .collect();
+        let chosen = MultiSelect::new()
+            .with_prompt("Select the branches to delete")
+            .items(&items)
+            .interact()?;
+        skipped += candidates.len() - chosen.len();
+        for index in chosen {
+            report(&candidates[index], attempt(&repo, &candidates[index]));
+        }
+    } else if yes && args.max_parallel > 1 {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(args.max_parallel as usize)
+            .build()?;
+        let progress = progress_bar("deleting", candidates.len() as u64);
+        // Workers push over their own connection and hand results back as they finish,
+        // reporting and logging stay on this thread
+        let path = repo.path().to_path_buf();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                pool.install(|| {
+                    candidates.par_iter().for_each_init(
+                        || (Repository::open(&path), sender.clone()),
+                        |(repo, sender), branch| {
+                            let result = match repo {
+                                Ok(repo) => attempt(repo, branch),
+                                Err(e) => Err(format!(
+                                    "failed to delete branch '{}': {}",
+                                    branch.name,
+                                    e.message()
+                                )),
+                            };
+                            // The receiver outlives the workers, sending can't fail
+                            let _ = sender.send((branch, result));
+                        },
+                    )
+                });
+                drop(sender);
+            });
+            for (branch, result) in receiver {
+                progress.set_message(branch.display_name());
+                progress.suspend(|| report(branch, result));
+                progress.inc(1);
+            }
+        });
+        progress.finish_and_clear();
+    } else if yes {
+        let progress = progress_bar("deleting", candidates.len() as u64);
+        for branch in &candidates {
+            progress.set_message(branch.display_name());
+            progress.suspend(|| report(branch, attempt(&repo, branch)));
+            progress.inc(1);
+        }
+        progress.finish_and_clear();
+    } else {
+        for branch in &candidates {
+            // Ask user if they want to delete this branch
+            if Confirm::new()
+                .with_prompt(format!(
+                    "Do you want to delete the branch '{}'?",
+                    branch.display_name()
+                ))
+                .interact()?
+            {
+                report(branch, attempt(&repo, branch));
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+
+    inform!(
+        "Deleted {} branches, {} skipped, {} failed",
+        deleted,
+        skipped,
+        failures.len()
+    );
+    if !failures.is_empty() {
+        eprintln!("{}", "Failed to delete:".red());
+        for failure in &failures {
+            eprintln!("  {}", failure);
+        }
+        return Err(HarvestError::DeletionFailures(failures.len()));
+    }
+    Ok(())
+}
+
+/// Removes what a remote-side cleanup leaves behind: remote-tracking branches of branches
+/// the server no longer has, and the local branches that were tracking them
+fn prune_local(source: &Source, args: &PruneLocalArgs) -> Result<(), HarvestError> {
+    if !args.dry_run && !args.yes && !std::io::stdin().is_terminal() {
+        return Err("stdin is not a terminal, pass --yes to delete without prompting".into());
+    }
+    let kept = match &args.keep_file {
+        Some(path) => load_keep_file(path)?,
+        None => HashSet::new(),
+    };
+    let repo = source.open_repo()?;
+    let mut remote = repo.find_remote(&source.remote).map_err(|_| {
+        HarvestError::NoRemote(format!("remote '{}' does not exist", source.remote))
+    })?;
+    let config = repo.config()?;
+    let live: HashSet<String> = list_remote_branches(&mut remote, &config, &source.remote)?
+        .into_iter()
+        .collect();
+
+    let tracking_prefix = format!("refs/remotes/{}/", source.remote);
+    let mut gone_tracking = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", tracking_prefix))? {
+        let reference = reference?;
+        let Some(name) = reference.name() else {
+            continue;
+        };
+        let branch = &name[tracking_prefix.len()..];
+        if branch != "HEAD" && !live.contains(branch) {
+            gone_tracking.push(name.to_string());
+        }
+    }
 
-    let mut branch_info = Vec::new();
-    for branch_result in branches {
-        let (branch, _) = branch_result.unwrap();
-        let branch_name = if let Some(name) = branch.name().unwrap() {
-            // Exclude the remote part from the branch name
-            name.strip_prefix("origin/").unwrap_or(name)
-        } else {
+    // Unless forced, only local branches whose work already landed are deleted
+    let target = if args.force {
+        None
+    } else {
+        Some(source.default_branch(&repo)?)
+    };
+    let mut skipped = 0;
+    let mut candidates = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let Some(name) = branch.name()? else {
+            continue;
+        };
+        // The upstream is read from the config, its remote-tracking branch may be gone already
+        let upstream_remote = config.get_string(&format!("branch.{}.remote", name));
+        let upstream = match config.get_string(&format!("branch.{}.merge", name)) {
+            Ok(merge) if upstream_remote.as_deref() == Ok(source.remote.as_str()) => merge,
+            _ => continue,
+        };
+        let upstream = upstream.strip_prefix("refs/heads/").unwrap_or(&upstream);
+        if live.contains(upstream) {
+            continue;
+        }
+        if kept.contains(name) {
+            inform!("{}", format!("kept (allowlist) {} (local)", name).yellow());
+            skipped += 1;
+            continue;
+        }
+        if branch.is_head() {
+            inform!(
+                "{}",
+                format!("skipping current branch {}, its upstream is gone", name).yellow()
+            );
+            skipped += 1;
+            continue;
+        }
+        let Some(tip) = branch.get().target() else {
             continue;
         };
+        if let Some(target) = target {
+            if !is_merged(&repo, tip, target) {
+                inform!(
+                    "{}",
+                    format!("skipping unmerged branch {} (use --force)", name).yellow()
+                );
+                skipped += 1;
+                continue;
+            }
+        }
+        candidates.push(name.to_string());
+    }
 
-        // Get the last commit of the branch
-        let commit = branch.get().peel_to_commit().unwrap();
-        let author = commit.author();
-        let author_name = author.name().unwrap_or("Unknown");
-        let author_email = author.email().unwrap_or("Unknown");
-        branch_info.push((
-            branch_name.to_string(),
-            author_name.to_string(),
-            author_email.to_string(),
-        ));
+    if args.dry_run {
+        for reference in &gone_tracking {
+            println!("would prune: {}", &reference["refs/remotes/".len()..]);
+        }
+        for name in &candidates {
+            println!("would delete: {} (local)", name);
+        }
+        inform!(
+            "{} remote-tracking branches would be pruned, {} local branches deleted, {} skipped",
+            gone_tracking.len(),
+            candidates.len(),
+            skipped
+        );
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+    let mut pruned = 0;
+    for reference in &gone_tracking {
+        let name = &reference["refs/remotes/".len()..];
+        debug!("pruning remote-tracking branch '{}'", name);
+        match repo.find_reference(reference).and_then(|mut r| r.delete()) {
+            Ok(()) => {
+                inform!("pruned {}", name);
+                pruned += 1;
+            }
+            Err(e) => {
+                let err = format!("failed to prune '{}': {}", name, e.message());
+                eprintln!("{}", err.red());
+                failures.push(err);
+            }
+        }
+    }
+    let mut deleted = 0;
+    for name in &candidates {
+        if !args.yes
+            && !Confirm::new()
+                .with_prompt(format!(
+                    "Do you want to delete the local branch '{}'?",
+                    name
+                ))
+                .interact()?
+        {
+            skipped += 1;
+            continue;
+        }
+        match delete_local_branch(&repo, name) {
+            Ok(()) => {
+                inform!("deleted {} (local)", name);
+                deleted += 1;
+            }
+            Err(err) => {
+                eprintln!("{}", err.to_string().red());
+                failures.push(err.to_string());
+            }
+        }
+    }
+
+    inform!(
+        "Pruned {} remote-tracking branches, deleted {} local branches, {} skipped, {} failed",
+        pruned,
+        deleted,
+        skipped,
+        failures.len()
+    );
+    if !failures.is_empty() {
+        eprintln!("{}", "Failed to delete:".red());
+        for failure in &failures {
+            eprintln!("  {}", failure);
+        }
+        return Err(HarvestError::DeletionFailures(failures.len()));
     }
+    Ok(())
+}
 
-    branch_info
+#[derive(Serialize)]
+struct ListEntry {
+    name: String,
+    author: String,
+    email: String,
+    date: String,
+    age: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merged: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ahead: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    behind: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    churn: Option<Churn>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committer_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    open_request: Option<String>,
 }
 
-fn delete_branch(repo_path: &Path, branch_name: String) -> Result<(), Box<dyn Error>> {
-    // Run the Git command in the specified directory
-    let output = Command::new("git")
-        .args(["push", "origin", "--delete", branch_name.as_str()])
-        .current_dir(repo_path) // Set the current directory for the command
-        .stdout(Stdio::piped()) // Capture standard output
-        .stderr(Stdio::piped()) // Capture standard error
-        .output()?;
+fn list(source: &Source, filter: &BranchFilter, args: &ListArgs) -> Result<(), HarvestError> {
+    let repo = source.open()?;
+    let target = match &args.merged_into {
+        Some(Some(spec)) => Some(resolve_commit(&repo, &source.remote, spec)?),
+        Some(None) => Some(source.default_branch(&repo)?),
+        None if args.merged_only || args.no_merged || args.csv => {
+            Some(source.default_branch(&repo)?)
+        }
+        None => None,
+    };
+    let show_created = args.show_created
+        || args
+            .format
+            .as_ref()
+            .is_some_and(|template| template.uses(Field::Created));
+    let show_churn = args.show_churn
+        || matches!(args.sort, Some(ListSort::Churn))
+        || args
+            .format
+            .as_ref()
+            .is_some_and(|template| template.uses(Field::Churn));
+    let show_ahead = args.show_ahead
+        || args
+            .format
+            .as_ref()
+            .is_some_and(|template| template.uses(Field::Ahead) || template.uses(Field::Behind));
+    let base = if show_ahead || show_created || show_churn {
+        Some(source.default_branch(&repo)?)
+    } else {
+        None
+    };
+    let mut branches: Vec<(BranchInfo, bool)> = source
+        .branches(&repo)?
+        .into_iter()
+        .filter(|branch| filter.matches(branch))
+        .map(|branch| {
+            let merged = target.is_some_and(|target| is_merged(&repo, branch.tip, target));
+            (branch, merged)
+        })
+        .filter(|(_, merged)| *merged || !args.merged_only)
+        .filter(|(_, merged)| !*merged || !args.no_merged)
+        .collect();
+    let total = branches.len();
+    // Computed for every branch up front, --sort churn needs them all
+    let mut churns = HashMap::new();
+    if let Some(base) = base.filter(|_| show_churn) {
+        for (branch, _) in &branches {
+            let churn = branch_churn(&repo, branch.tip, base)?;
+            churns.insert(cache_key(&branch.name, branch.kind), churn);
+        }
+    }
+    let churn = |branch: &BranchInfo| churns.get(&cache_key(&branch.name, branch.kind)).copied();
+    // Without --sort, show the stalest branches when only part of the list fits
+    let sort = args.sort.or(args.limit.map(|_| ListSort::Age));
+    match sort {
+        Some(ListSort::Name) => branches.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name)),
+        Some(ListSort::Date) => branches.sort_by_key(|(branch, _)| -branch.time.seconds()),
+        Some(ListSort::Age) => branches.sort_by_key(|(branch, _)| branch.time.seconds()),
+        Some(ListSort::Author) => branches.sort_by(|(a, _), (b, _)| {
+            a.author_email
+                .to_lowercase()
+                .cmp(&b.author_email.to_lowercase())
+                .then_with(|| a.name.cmp(&b.name))
+        }),
+        Some(ListSort::Churn) => branches.sort_by_key(|(branch, _)| {
+            std::cmp::Reverse(churn(branch).map_or(0, |churn| churn.added + churn.removed))
+        }),
+        None => {}
+    }
+    if args.reverse {
+        branches.reverse();
+    }
+    if let Some(limit) = args.limit {
+        branches.truncate(limit);
+    }
+
+    // Ahead and behind of the default branch, both from one walk
+    let ahead = |branch: &BranchInfo| match base.filter(|_| show_ahead) {
+        Some(base) => ahead_behind(&repo, branch.tip, base).map(Some),
+        None => Ok(None),
+    };
+    // The outer None is for not asked, the inner one for a branch without commits of its own
+    let created = |branch: &BranchInfo| match base.filter(|_| show_created) {
+        Some(base) => created_time(&repo, branch.tip, base)
+            .map(|time| Some(time.map(|time| date::format_date(&time)))),
+        None => Ok(None),
+    };
 
-    if !output.status.success() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to delete branch",
-        )));
+    let requests = open_requests(&repo, source, &args.forge);
+    let request = |branch: &BranchInfo| {
+        requests
+            .as_ref()
+            .filter(|_| branch.kind == BranchType::Remote)
+            .and_then(|requests| requests.get(&branch.name))
+            .cloned()
+    };
+
+    let now = date::now();
+    if args.json {
+        let mut entries = Vec::new();
+        for (branch, merged) in &branches {
+            let ahead_behind = ahead(branch)?;
+            entries.push(ListEntry {
+                name: branch.display_name(),
+                author: branch.author_name.clone(),
+                email: branch.author_email.clone(),
+                date: date::format_date(&branch.time),
+                age: date::age_days(&branch.time, now),
+                merged: target.map(|_| *merged),
+                ahead: ahead_behind.map(|(ahead, _)| ahead),
+                behind: ahead_behind.map(|(_, behind)| behind),
+                created: created(branch)?.flatten(),
+                churn: churn(branch),
+                committer: Some(branch.committer_name.clone()).filter(|_| args.show_committer),
+                committer_email: Some(branch.committer_email.clone())
+                    .filter(|_| args.show_committer),
+                open_request: request(branch),
+            });
+        }
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    if args.csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        let mut header = vec![
+            "name",
+            "author_name",
+            "author_email",
+            "last_commit_date",
+            "age_days",
+            "merged",
+        ];
+        if show_ahead {
+            header.extend(["ahead", "behind"]);
+        }
+        if show_created {
+            header.push("created");
+        }
+        if show_churn {
+            header.extend(["lines_added", "lines_removed"]);
+        }
+        if args.show_committer {
+            header.extend(["committer_name", "committer_email"]);
+        }
+        writer.write_record(header)?;
+        for (branch, merged) in &branches {
+            let mut record = vec![
+                branch.display_name(),
+                branch.author_name.clone(),
+                branch.author_email.clone(),
+                date::format_date(&branch.time),
+                date::age_days(&branch.time, now).to_string(),
+                merged.to_string(),
+            ];
+            if let Some((ahead, behind)) = ahead(branch)? {
+                record.push(ahead.to_string());
+                record.push(behind.to_string());
+            }
+            if let Some(created) = created(branch)? {
+                record.push(created.unwrap_or_default());
+            }
+            if let Some(churn) = churn(branch) {
+                record.push(churn.added.to_string());
+                record.push(churn.removed.to_string());
+            }
+            if args.show_committer {
+                record.push(branch.committer_name.clone());
+                record.push(branch.committer_email.clone());
+            }
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+    for (branch, merged) in &branches {
+        let created_date = created(branch)?;
+        let ahead_behind = ahead(branch)?;
+        if let Some(template) = &args.format {
+            println!(
+                "{}",
+                template.render(|field| match field {
+                    Field::Name => branch.display_name(),
+                    Field::Email => branch.author_email.clone(),
+                    Field::Author => branch.author_name.clone(),
+                    Field::Date => date::format_date(&branch.time),
+                    Field::Age => date::age_days(&branch.time, now).to_string(),
+                    Field::Created => created_date
+                        .clone()
+                        .flatten()
+                        .unwrap_or_else(|| "-".to_string()),
+                    Field::Churn => churn(branch)
+                        .map_or(0, |churn| churn.added + churn.removed)
+                        .to_string(),
+                    Field::Ahead => ahead_behind.map_or(0, |(ahead, _)| ahead).to_string(),
+                    Field::Behind => ahead_behind.map_or(0, |(_, behind)| behind).to_string(),
+                })
+            );
+            continue;
+        }
+        let mut line = branch.display_name();
+        if !args.no_date {
+            let when = if args.relative {
+                date::format_relative(&branch.time, now)
+            } else {
+                date::format_date(&branch.time)
+            };
+            line = format!("{}  {}", line, when.dimmed());
+        }
+        if args.show_committer {
+            line = format!("{}  {}", line, branch.display_identity().cyan());
+        }
+        if *merged && !args.merged_only {
+            line = format!("{}  {}", line, "(merged)".green());
+        }
+        if let Some(created) = created_date {
+            let created = created.unwrap_or_else(|| "-".to_string());
+            line = format!("{}  {}", line, format!("created {}", created).dimmed());
+        }
+        if let Some(churn) = churn(branch) {
+            let changes = format!("+{} -{}", churn.added, churn.removed);
+            line = format!("{}  {}", line, changes.dimmed());
+        }
+        if let Some(request) = request(branch) {
+            line = format!("{}  {}", line, request.magenta());
+        }
+        if let Some((ahead, behind)) = ahead_behind {
+            let ahead = match ahead {
+                0 => "0 ahead".green(),
+                ahead => format!("{} ahead", ahead).yellow(),
+            };
+            let behind = format!("{} behind", behind).dimmed();
+            line = format!("{}  {}, {}", line, ahead, behind);
+        }
+        println!("{}", line);
+    }
+    if total > branches.len() {
+        inform!("... and {} more", total - branches.len());
+    }
+    // Templates are meant for scripts, they get nothing but the branches
+    if args.format.is_none() {
+        match total {
+            0 => inform!("no branches matched {}", filter.describe()),
+            1 => inform!("1 branch matched"),
+            _ => inform!("{} branches matched", total),
+        }
     }
 
     Ok(())
 }
 
-fn stats(repo_location: &str) -> Result<(), Box<dyn Error>> {
-    let repo = get_repo(repo_location.to_string());
-    let branches = get_remote_branches(repo);
-    let mut branch_count = HashMap::new();
-    let total_branches = branches.len();
-    for (_, _, email) in branches {
-        *branch_count.entry(email).or_insert(0) += 1;
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Warn,
+    Critical,
+}
+
+#[derive(Serialize)]
+struct StaleEntry {
+    name: String,
+    email: String,
+    age_days: i64,
+    severity: Severity,
+}
+
+fn stale(source: &Source, days: u64, args: &StaleArgs) -> Result<(), HarvestError> {
+    if args.critical_days < days {
+        return Err(HarvestError::InvalidFilter(format!(
+            "--critical-days {} is below the stale threshold of {} days",
+            args.critical_days, days
+        )));
     }
+    let repo = source.open()?;
+    let now = date::now();
+    let mut branches: Vec<BranchInfo> = source
+        .branches(&repo)?
+        .into_iter()
+        .filter(|branch| date::age_days(&branch.time, now) > date::days(days))
+        .filter(|branch| excluded_email(&args.exclude_email, &branch.author_email).is_none())
+        .collect();
+    branches.sort_by_key(|branch| branch.time.seconds());
 
-    println!("Branches per user:");
-    for (email, count) in branch_count {
-        println!("{}: {}", email, count);
+    if args.json {
+        let entries: Vec<StaleEntry> = branches
+            .iter()
+            .map(|branch| {
+                let age_days = date::age_days(&branch.time, now);
+                StaleEntry {
+                    name: branch.display_name(),
+                    email: branch.author_email.clone(),
+                    age_days,
+                    severity: if age_days >= date::days(args.critical_days) {
+                        Severity::Critical
+                    } else {
+                        Severity::Warn
+                    },
+                }
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return check_stale_limit(&branches, now, args.fail_if);
     }
+
+    let name_width = branches
+        .iter()
+        .map(|branch| branch.display_name().chars().count())
+        .chain(["BRANCH".len()])
+        .max()
+        .unwrap_or(0);
+    let email_width = branches
+        .iter()
+        .map(|branch| branch.author_email.chars().count())
+        .chain(["AUTHOR".len()])
+        .max()
+        .unwrap_or(0);
     println!(
-        "=========================\n Total Remote Branches: {}",
-        total_branches
+        "{}",
+        format!(
+            "{:<name_width$}  {:<email_width$}  {}",
+            "BRANCH",
+            "AUTHOR",
+            if args.relative {
+                "LAST COMMIT"
+            } else {
+                "AGE (DAYS)"
+            }
+        )
+        .bold()
     );
-    Ok(())
+    for branch in &branches {
+        let age = if args.relative {
+            date::format_relative(&branch.time, now)
+        } else {
+            date::age_days(&branch.time, now).to_string()
+        };
+        println!(
+            "{:<name_width$}  {:<email_width$}  {}",
+            branch.display_name(),
+            branch.author_email.cyan(),
+            age.yellow()
+        );
+    }
+    check_stale_limit(&branches, now, args.fail_if)
 }
 
-fn cleanup(repo_location: &str, filter_email: &str) -> Result<(), Box<dyn Error>> {
-    let repo_path = Path::new(repo_location);
-    let repo = get_repo(repo_location.to_string());
-    let branches = get_remote_branches(repo);
-    for (branch_name, _, email) in branches {
-        if email == filter_email {
-            // Ask user if they want to delete this branch
-            if Confirm::new()
-                .with_prompt(format!(
-                    "Do you want to delete the branch '{}'?",
-                    branch_name
-                ))
-                .interact()?
+/// Fails when there are more stale branches than --fail-if allows, naming them on stderr
+/// so a CI log shows them whatever happens to stdout
+fn check_stale_limit(
+    branches: &[BranchInfo],
+    now: i64,
+    limit: Option<usize>,
+) -> Result<(), HarvestError> {
+    let Some(limit) = limit.filter(|limit| branches.len() > *limit) else {
+        return Ok(());
+    };
+    for branch in branches {
+        eprintln!(
+            "{}",
+            format!(
+                "stale: {} by {}, {} days",
+                branch.display_name(),
+                branch.author_email,
+                date::age_days(&branch.time, now)
+            )
+            .red()
+        );
+    }
+    Err(HarvestError::TooManyStale(branches.len(), limit))
+}
+
+#[derive(Serialize)]
+struct OrphanEntry {
+    name: String,
+    email: String,
+    date: String,
+    reason: &'static str,
+}
+
+/// Local branches without an upstream, and remote branches named against the convention
+fn orphans(
+    source: &Source,
+    protected: &GlobSet,
+    naming: &[String],
+    args: &OrphansArgs,
+) -> Result<(), HarvestError> {
+    let repo = source.open()?;
+    let config = repo.config()?;
+    let mut builder = GlobSetBuilder::new();
+    for pattern in naming {
+        builder.add(Glob::new(pattern).map_err(|e| {
+            HarvestError::InvalidFilter(format!("invalid --naming pattern '{}': {}", pattern, e))
+        })?);
+    }
+    let convention = builder.build()?;
+
+    let mut local = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let Some(name) = branch.name()?.map(str::to_string) else {
+            continue;
+        };
+        if branch.upstream().is_ok() {
+            continue;
+        }
+        // An upstream set in the config whose remote-tracking branch is gone is prune-local's
+        let reason = match config.get_string(&format!("branch.{}.merge", name)) {
+            Ok(_) => "upstream gone",
+            Err(_) => "no upstream",
+        };
+        let Ok(commit) = branch.get().peel_to_commit() else {
+            continue;
+        };
+        let email = commit.author().email().unwrap_or("Unknown").to_string();
+        local.push(OrphanEntry {
+            name: format!("{} (local)", name),
+            email,
+            date: date::format_date(&commit.time()),
+            reason,
+        });
+    }
+
+    let mut misnamed = Vec::new();
+    if !naming.is_empty() {
+        for branch in source.branches(&repo)? {
+            if branch.kind != BranchType::Remote
+                || convention.is_match(&branch.name)
+                || protected.is_match(&branch.name)
             {
-                delete_branch(repo_path, branch_name)?;
+                continue;
             }
+            misnamed.push(OrphanEntry {
+                name: branch.name.clone(),
+                email: branch.author_email.clone(),
+                date: date::format_date(&branch.time),
+                reason: "naming",
+            });
         }
     }
+    local.sort_by(|a, b| a.name.cmp(&b.name));
+    misnamed.sort_by(|a, b| a.name.cmp(&b.name));
 
+    if args.json {
+        local.extend(misnamed);
+        println!("{}", serde_json::to_string_pretty(&local)?);
+        return Ok(());
+    }
+    println!("{}", "Local branches without an upstream:".bold());
+    if local.is_empty() {
+        println!("  none");
+    }
+    for entry in &local {
+        println!(
+            "  {}  {}  {}",
+            entry.name,
+            entry.date.dimmed(),
+            entry.reason.yellow()
+        );
+    }
+    if naming.is_empty() {
+        inform!(
+            "remote branch names were not checked, pass --naming or set naming in the config file"
+        );
+        return Ok(());
+    }
+    println!(
+        "{}",
+        format!("Remote branches not named {}:", naming.join(", ")).bold()
+    );
+    if misnamed.is_empty() {
+        println!("  none");
+    }
+    for entry in &misnamed {
+        println!(
+            "  {}  {}  {}",
+            entry.name,
+            entry.email.cyan(),
+            entry.date.dimmed()
+        );
+    }
     Ok(())
 }
 
-fn list(repo_location: &str, filter_email: &str) -> Result<(), Box<dyn Error>> {
-    let repo = get_repo(repo_location.to_string());
-    let branches = get_remote_branches(repo);
-    for (branch_name, _, email) in branches {
-        if email == filter_email {
-            println!("{}", branch_name);
+#[derive(Clone, Copy, ValueEnum)]
+enum ListSort {
+    /// branch name, alphabetically
+    Name,
+    /// last commit date, newest first
+    Date,
+    /// age of the last commit, oldest first
+    Age,
+    /// author email, then branch name
+    Author,
+    /// lines added and removed against the default branch, biggest first; diffs every branch
+    Churn,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Paginate {
+    /// page list and stale output when writing to a terminal
+    Auto,
+    /// always page list and stale output
+    Always,
+    /// never page the output
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ErrorFormat {
+    /// "error: " and the message
+    Text,
+    /// a JSON object with the message and its kind, on one line
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorMode {
+    /// color when writing to a terminal and NO_COLOR is not set
+    Auto,
+    /// always color the output
+    Always,
+    /// never color the output
+    Never,
+}
+
+fn init_color(mode: ColorMode) {
+    let enabled = match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
         }
-    }
+    };
+    colored::control::set_override(enabled);
+}
 
-    Ok(())
+/// -v traces this tool's own decisions, -vv also those of its dependencies
+fn init_logging(verbose: u8) {
+    let mut builder = env_logger::Builder::new();
+    match verbose {
+        0 => builder.filter_level(LevelFilter::Warn),
+        1 => builder
+            .filter_level(LevelFilter::Warn)
+            .filter_module(module_path!(), LevelFilter::Debug),
+        _ => builder.filter_level(LevelFilter::Trace),
+    };
+    // Only the first run in a process sets the logger, the tests run several
+    let _ = builder
+        .format_timestamp(None)
+        .format_target(false)
+        .try_init();
 }
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(
+    after_help = "Defaults can be set in a .git-harvest.toml file in the repository root or in \
+                  $XDG_CONFIG_HOME/git-harvest/. Flags take precedence over the repository \
+                  file, which takes precedence over the user file.\n\n\
+                  Pushes and fetches over HTTPS authenticate with the token in \
+                  $GIT_HARVEST_TOKEN when it is set, or in $GITHUB_TOKEN for GitHub \
+                  remotes, before the git credential helper."
+)]
 struct Cli {
-    /// Set the location of the repository
+    /// Set the location of the repository, stats can be given several to sum them up
     #[arg(short, long, value_name = ".")]
-    location: Option<String>,
+    location: Vec<String>,
+
+    /// Sum up the stats of every repository directly inside this directory
+    #[arg(long, value_name = "DIR")]
+    scan: Option<String>,
+
+    /// Name of the remote whose branches are inspected
+    #[arg(short, long, value_name = "origin")]
+    remote: Option<String>,
+
+    /// Also include local branches, not only the ones on the remote
+    #[arg(long)]
+    include_local: bool,
+
+    /// Also list symbolic refs such as origin/HEAD, which are skipped by default as they
+    /// only repeat the branch they point at
+    #[arg(long)]
+    include_symbolic: bool,
+
+    /// Fetch and prune the remote before reading its branches
+    #[arg(long)]
+    fetch: bool,
+
+    /// Read the branch names straight from this URL, like git ls-remote, instead of from a
+    /// clone. No commits are fetched, so there are no authors or dates: only stats
+    /// --by-prefix and list work this way
+    #[arg(long, value_name = "URL", conflicts_with_all = ["location", "scan", "remote", "fetch"])]
+    remote_url: Option<String>,
+
+    /// Attribute branches to the author of their last commit, or to its committer, who
+    /// differs when the commit was rebased, cherry-picked or applied from a patch
+    #[arg(long, value_enum, default_value_t = Identity::Author)]
+    by: Identity,
+
+    /// Branch that merged detection and ahead counts compare against, by default the one the
+    /// remote's HEAD points at, or main or master when the remote has no HEAD
+    #[arg(long, value_name = "BRANCH")]
+    default_branch: Option<String>,
+
+    /// Remember the author and date of each branch tip in this file, so later runs only
+    /// load the tips that moved; delete it after editing .mailmap
+    #[arg(long, value_name = "PATH")]
+    cache: Option<PathBuf>,
+
+    /// Only print the actual output, without banners, notes, skipped branches or summaries
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Print debug traces, repeat for more detail
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// When to color the output
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// When to send list and stale output through $PAGER (less by default), auto does when
+    /// writing to a terminal
+    #[arg(long, value_enum, default_value_t = Paginate::Auto)]
+    paginate: Paginate,
+
+    /// How to print the error a run fails with; json prints an object with the message and a
+    /// kind such as NotARepo, NoRemote, AuthFailed or BranchNotFound, Other for the rest
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
 
     #[command(subcommand)]
-    command: Option<Commands>,
+    command: Commands,
+}
+
+/// The trailing PATH of the subcommands reading a repository, an alternative to --location
+#[derive(Args)]
+struct RepositoryPath {
+    /// repository to operate on, like --location
+    #[arg(value_name = "PATH")]
+    path: Option<String>,
+}
+
+#[derive(Args)]
+struct FilterArgs {
+    /// use the email to filter the branches by author, case-insensitive exact match
+    /// by default, or a whole domain when given as "@corp.com"
+    #[arg(short, long, visible_alias = "author-email")]
+    email: Option<String>,
+    /// only match branches whose last commit was committed by this email, exactly or as
+    /// "@corp.com"; given without --email it matches any author, with it both have to match
+    #[arg(long, value_name = "EMAIL")]
+    committer_email: Option<String>,
+    /// match any author email containing the --email value
+    #[arg(long, requires = "email")]
+    email_contains: bool,
+    /// treat the --email value as a case-insensitive regex searched for in each author
+    /// email, e.g. '\+bot@'; anchor it with ^ and $ to match whole emails
+    #[arg(long, requires = "email", conflicts_with = "email_contains")]
+    email_regex: bool,
+    /// drop branches whose author email matches this, exactly or as "@corp.com"; can be
+    /// repeated, and wins over --email
+    #[arg(long, value_name = "EMAIL")]
+    exclude_email: Vec<String>,
+    /// only match branches whose author name contains this, case-insensitive; given without
+    /// --email it matches any email, with it both have to match
+    #[arg(long)]
+    name: Option<String>,
+    /// only match branches whose last commit is older than this many days
+    #[arg(long, value_name = "DAYS")]
+    older_than: Option<u64>,
+    /// only match branches whose last commit is newer than this many days; with
+    /// --older-than, the branches in between
+    #[arg(long, value_name = "DAYS")]
+    newer_than: Option<u64>,
+    /// only match branches whose name matches this glob, e.g. 'feature/*'; the name is
+    /// matched without the remote prefix
+    #[arg(long, value_name = "GLOB")]
+    pattern: Option<String>,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// print the stats as JSON
+    #[arg(long)]
+    json: bool,
+    /// count branches per name prefix (the part before the first "/") instead of per user
+    #[arg(long)]
+    by_prefix: bool,
+    /// count branches per month of their last commit instead of per user, oldest month first
+    #[arg(long, conflicts_with = "by_prefix")]
+    by_month: bool,
+    /// count branches per age of their last commit, from 0-7 days up to over a year, instead
+    /// of per user
+    #[arg(long, conflicts_with_all = ["by_prefix", "by_month"])]
+    age_buckets: bool,
+    /// also sum the commits each group has that the default branch does not, walks every
+    /// branch's history
+    #[arg(long)]
+    show_ahead: bool,
+    /// also count the distinct commits each group has that the default branch does not,
+    /// shared commits of stacked branches counting once; walks every branch's history
+    #[arg(long)]
+    commits: bool,
+    /// with several repositories, print the stats of each one before the combined ones
+    #[arg(long)]
+    per_repo: bool,
+    /// also count the distinct commits the branches point at, ranking by that count so
+    /// branches sharing a tip don't inflate a group
+    #[arg(long)]
+    unique_tips: bool,
+    /// only count branches whose last commit is on this day or later, as YYYY-MM-DD
+    #[arg(long, value_name = "DATE", value_parser = date::parse_date)]
+    since: Option<i64>,
+    /// only count branches whose last commit is on this day or earlier, as YYYY-MM-DD
+    #[arg(long, value_name = "DATE", value_parser = date::parse_date)]
+    until: Option<i64>,
+    /// only count branches whose last commit is older than this many days
+    #[arg(long, value_name = "DAYS")]
+    older_than: Option<u64>,
+    /// only count branches whose last commit is newer than this many days; with
+    /// --older-than, the branches in between
+    #[arg(long, value_name = "DAYS")]
+    newer_than: Option<u64>,
+    /// also append this run's total and per-author counts to a SQLite database, created
+    /// when missing, to follow them over time
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<PathBuf>,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct ForgeArgs {
+    /// ask GitHub or GitLab, found from the remote's URL, which branches have an open pull
+    /// or merge request; needs curl, and going without when the API can't be reached
+    #[arg(long)]
+    check_prs: bool,
+    /// GitHub token for --check-prs [default: $GITHUB_TOKEN]
+    #[arg(long, value_name = "TOKEN", requires = "check_prs")]
+    github_token: Option<String>,
+    /// GitLab token for --check-prs [default: $GITLAB_TOKEN]
+    #[arg(long, value_name = "TOKEN", requires = "check_prs")]
+    gitlab_token: Option<String>,
+    /// host of a self-hosted GitLab to check, besides gitlab.com; the GitLab token is only
+    /// sent to these (repeatable)
+    #[arg(long, value_name = "HOST", requires = "check_prs")]
+    gitlab_host: Vec<String>,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    forge: ForgeArgs,
+    /// only print the branch names, without the last commit date
+    #[arg(long)]
+    no_date: bool,
+    /// print at most this many branches, the first ones in --sort order, oldest last commit
+    /// first by default
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    /// order the branches by this instead of as git lists them
+    #[arg(long, value_enum)]
+    sort: Option<ListSort>,
+    /// reverse the order of the branches, applied before --limit
+    #[arg(long)]
+    reverse: bool,
+    /// mark branches already merged into this branch, the default branch when none is given
+    #[arg(long, value_name = "BRANCH", num_args = 0..=1)]
+    merged_into: Option<Option<String>>,
+    /// only show branches already merged into --merged-into (the default branch by default)
+    #[arg(long)]
+    merged_only: bool,
+    /// only show branches not merged into --merged-into (the default branch by default),
+    /// the ones with work still to review or merge
+    #[arg(long, conflicts_with = "merged_only")]
+    no_merged: bool,
+    /// print each branch with a template of {name}, {email}, {author}, {date}, {age} (in
+    /// days), {created}, {churn}, {ahead} and {behind}, e.g. '{age}d {name} <{email}>'
+    #[arg(long, value_name = "TEMPLATE", value_parser = Template::parse)]
+    format: Option<Template>,
+    /// print the branches as CSV, merged is relative to --merged-into (the default branch by
+    /// default)
+    #[arg(long, conflicts_with = "format")]
+    csv: bool,
+    /// print the branches as a JSON array of objects with name, author, email, date and age
+    /// (in days), plus merged with --merged-into, ahead and behind with --show-ahead, created
+    /// with --show-created, churn with --show-churn and committer and committer_email with
+    /// --show-committer
+    #[arg(long, conflicts_with_all = ["format", "csv"])]
+    json: bool,
+    /// show how many commits each branch has that the default branch does not, and how many
+    /// it lacks that the default branch has; walks every branch's history
+    #[arg(long)]
+    show_ahead: bool,
+    /// show when each branch was started, the date of its first commit the default branch
+    /// does not have; walks every branch's history
+    #[arg(long)]
+    show_created: bool,
+    /// show the author of each branch's last commit, and its committer when someone else
+    /// committed it, as rebases and cherry-picks do
+    #[arg(long)]
+    show_committer: bool,
+    /// show how many lines each branch adds and removes against its merge base with the
+    /// default branch, as +added -removed; diffs every branch
+    #[arg(long)]
+    show_churn: bool,
+    /// show when the last commit was relative to now, e.g. "3 weeks ago", instead of its
+    /// date; --format, --csv and --json keep the dates
+    #[arg(long)]
+    relative: bool,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct CleanupArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    forge: ForgeArgs,
+    /// print the branches that would be deleted without deleting them
+    #[arg(long)]
+    dry_run: bool,
+    /// with --dry-run, print the plan as a JSON array with the name, email, age_days,
+    /// merged, protected and would_delete of every matching branch
+    #[arg(long, requires = "dry_run")]
+    json: bool,
+    /// delete every matching branch without asking for confirmation; past the config file's
+    /// bulk_threshold (20 by default) the number of branches has to be typed in
+    #[arg(short, long)]
+    yes: bool,
+    /// let --yes delete any number of branches without typing their count in
+    #[arg(long)]
+    force_bulk: bool,
+    /// never delete branches matching this glob, in addition to main, master,
+    /// develop and release
+    #[arg(long, value_name = "GLOB")]
+    protect: Vec<String>,
+    /// drop the built-in protection of main, master, develop and release, as well as the
+    /// globs protected by the config file
+    #[arg(long)]
+    no_protect: bool,
+    /// also delete branches that are not merged into the default branch, or that have an
+    /// open request with --check-prs
+    #[arg(long)]
+    force: bool,
+    /// only delete branches without a commit the default branch lacks, whoever authored
+    /// them unless --email is given; nothing of theirs is lost, even with --force
+    #[arg(long)]
+    only_zero_ahead: bool,
+    /// pick the branches to delete from a list instead of confirming each one
+    #[arg(long, conflicts_with_all = ["yes", "dry_run"])]
+    select: bool,
+    /// tag each branch as archive/<branch> (pushed to the remote for remote branches) before
+    /// deleting it, so it can be brought back with `git-harvest restore <branch>`
+    #[arg(long)]
+    archive: bool,
+    /// retry a delete failing on a network error this many times, waiting longer each time
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+    /// append a JSON line per deleted or failed branch to this file, as an audit trail
+    #[arg(long, value_name = "PATH")]
+    log: Option<PathBuf>,
+    /// also delete the checked-out branch and the remote branch it tracks, which are
+    /// skipped otherwise
+    #[arg(long)]
+    allow_current: bool,
+    /// delete up to N branches at once, each over its own connection; needs --yes since the
+    /// prompts ask about one branch at a time
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    max_parallel: u32,
+    /// never delete the branches named in this file, one per line, # starts a comment
+    #[arg(long, value_name = "PATH")]
+    keep_file: Option<PathBuf>,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct PruneLocalArgs {
+    /// print what would be pruned and deleted without changing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// delete every local branch whose upstream is gone without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+    /// also delete local branches that are not merged into the default branch
+    #[arg(long)]
+    force: bool,
+    /// never delete the branches named in this file, one per line, # starts a comment
+    #[arg(long, value_name = "PATH")]
+    keep_file: Option<PathBuf>,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct StaleArgs {
+    /// branches whose last commit is older than this many days are stale [default: 90]
+    #[arg(short, long)]
+    days: Option<u64>,
+    /// drop branches whose author email matches this, exactly or as "@corp.com"; can be
+    /// repeated
+    #[arg(long, value_name = "EMAIL")]
+    exclude_email: Vec<String>,
+    /// print the stale branches as a JSON array of objects with name, email, age_days and
+    /// severity, warn past --days and critical past --critical-days
+    #[arg(long)]
+    json: bool,
+    /// branches whose last commit is at least this many days old are critical in --json
+    #[arg(long, value_name = "DAYS", default_value_t = 365)]
+    critical_days: u64,
+    /// show the age of the last commit relative to now, e.g. "3 months ago", instead of in
+    /// days; --json keeps the days
+    #[arg(long)]
+    relative: bool,
+    /// exit with status 4 when more than N branches are stale, listing them on stderr, for
+    /// CI jobs enforcing a limit
+    #[arg(long, value_name = "N")]
+    fail_if: Option<usize>,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the value of a key, the one in effect unless --user is given; protect and naming
+    /// print one glob per line
+    Get {
+        /// location, remote, protect, stale_days, bulk_threshold or naming
+        key: String,
+        /// read the user file only
+        #[arg(long)]
+        user: bool,
+    },
+    /// Set a key, keeping the rest of the file and its comments as they are
+    Set {
+        /// location, remote, protect, stale_days, bulk_threshold or naming
+        key: String,
+        /// the new value, give protect and naming every glob they should hold
+        #[arg(required = true)]
+        values: Vec<String>,
+        /// write to the user file instead of the repository's
+        #[arg(long)]
+        user: bool,
+    },
+}
+
+#[derive(Args)]
+struct OrphansArgs {
+    /// glob remote branch names are expected to match, e.g. 'feature/*'; can be repeated and
+    /// adds to the config file's naming, remote branches are only checked when there is one
+    #[arg(long, value_name = "GLOB")]
+    naming: Vec<String>,
+    /// print the orphans as a JSON array of objects with name, email, date and reason, one of
+    /// "no upstream", "upstream gone" and "naming"
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct TagsArgs {
+    /// print the stats as JSON
+    #[arg(long)]
+    json: bool,
+    /// number of oldest tags to show
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    oldest: usize,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Args)]
+struct BrowseArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
+    /// delete branches that are not merged into the default branch after a single
+    /// confirmation, instead of asking a second time
+    #[arg(long)]
+    force: bool,
+    /// never delete the branches named in this file, one per line, # starts a comment
+    #[arg(long, value_name = "PATH")]
+    keep_file: Option<PathBuf>,
+    /// retry a delete failing on a network error this many times, waiting longer each time
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct RenameArgs {
+    /// current name of the branch, without the remote prefix
+    old: String,
+    /// new name of the branch
+    new: String,
+    /// print the rename without pushing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// rename without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+    /// retry a push failing on a network error this many times, waiting longer each time
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+}
+
+#[derive(Args)]
+struct OffboardArgs {
+    /// email of the departing author, exactly or as "@corp.com"
+    #[arg(long, value_name = "EMAIL")]
+    from: String,
+    /// namespace adopted branches are moved under [default: the local part of user.email]
+    #[arg(long, value_name = "NAME")]
+    namespace: Option<String>,
+    /// only list the author's branches
+    #[arg(long)]
+    dry_run: bool,
+    /// retry a push failing on a network error this many times, waiting longer each time
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+    #[command(flatten)]
+    repository: RepositoryPath,
+}
+
+#[derive(Args)]
+struct CompareArgs {
+    /// email of the first author, exactly or as "@corp.com"
+    #[arg(long, value_name = "EMAIL")]
+    a: String,
+    /// email of the second author, exactly or as "@corp.com"
+    #[arg(long, value_name = "EMAIL")]
+    b: String,
+    /// print the comparison as JSON
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    repository: RepositoryPath,
 }
 
+const DELETION_EXIT_STATUS: &str = "Exit status:\n  \
+    0  every branch picked for deletion was deleted, or none matched\n  \
+    1  nothing was deleted because of an error, e.g. no repository or an unreadable file\n  \
+    2  invalid command line\n  \
+    3  some branches could not be deleted, the others were";
+
+const STALE_EXIT_STATUS: &str = "Exit status:\n  \
+    0  the stale branches were listed, and there are no more than --fail-if of them\n  \
+    1  an error kept the branches from being read\n  \
+    2  invalid command line\n  \
+    4  more branches are stale than --fail-if allows";
+
 #[derive(Subcommand)]
 enum Commands {
     /// Stats about the current repo
-    Stats {},
-    /// List remote branches
-    List {
-        /// use the email to filter the branches by author
-        #[arg(short, long)]
-        email: Option<String>,
+    Stats(StatsArgs),
+    /// Stats about the tags of the current repo: per tagger, annotated or lightweight, and
+    /// the oldest ones
+    Tags(TagsArgs),
+    /// Authors with the most branches
+    Top {
+        /// number of authors to show
+        #[arg(short, long, default_value_t = 10)]
+        n: usize,
+        #[command(flatten)]
+        repository: RepositoryPath,
     },
+    /// List remote branches
+    List(ListArgs),
+    /// Compare the branches of two authors: the ones only each of them worked on, and the
+    /// ones they share
+    Compare(CompareArgs),
     /// Delete remote branches that are no more needed
-    Cleanup {
-        /// use the email to filter the branches by author
-        #[arg(short, long)]
-        email: Option<String>,
+    #[command(after_help = DELETION_EXIT_STATUS)]
+    Cleanup(CleanupArgs),
+    /// Prune remote-tracking branches the remote no longer has, and delete the local branches
+    /// that were tracking them
+    #[command(after_help = DELETION_EXIT_STATUS)]
+    PruneLocal(PruneLocalArgs),
+    /// List every author's branches that have not seen a commit in a while, oldest first
+    #[command(after_help = STALE_EXIT_STATUS)]
+    Stale(StaleArgs),
+    /// List local branches without an upstream, and remote branches whose name does not
+    /// follow the team's naming convention
+    Orphans(OrphansArgs),
+    /// Recreate a deleted remote branch from its archive/<branch> tag, see cleanup --archive
+    Restore {
+        /// name of the branch to recreate, without the remote prefix
+        branch: String,
+        /// recreate the branch at this commit instead of at its archive tag
+        #[arg(long, value_name = "COMMIT")]
+        commit: Option<String>,
+    },
+    /// Rename a remote branch, pushing it under the new name and deleting the old one
+    Rename(RenameArgs),
+    /// Browse the branches in a terminal UI: filter by typing, sort by column, pick branches
+    /// and delete them
+    #[cfg(feature = "tui")]
+    Browse(BrowseArgs),
+    /// Go through a departing author's remote branches, deleting, archiving or adopting each
+    /// one into your own namespace
+    Offboard(OffboardArgs),
+    /// Print the git user.name and user.email used as the default filter, and where they are set
+    Whoami,
+    /// Read or change the settings of .git-harvest.toml, in the repository or for the user
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print a shell completion script to stdout
+    Completions {
+        /// shell to generate the completions for
+        shell: Shell,
     },
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
-    let location = cli.location.unwrap_or(".".to_string());
-    let (_, user_email) = get_current_user_info();
+impl Commands {
+    /// The repository given as a trailing PATH, if any
+    fn repository_path(&self) -> Option<&str> {
+        let repository = match self {
+            Commands::Stats(args) => &args.repository,
+            Commands::Tags(args) => &args.repository,
+            Commands::Top { repository, .. } => repository,
+            Commands::List(args) => &args.repository,
+            Commands::Compare(args) => &args.repository,
+            Commands::Cleanup(args) => &args.repository,
+            Commands::PruneLocal(args) => &args.repository,
+            Commands::Stale(args) => &args.repository,
+            Commands::Orphans(args) => &args.repository,
+            Commands::Offboard(args) => &args.repository,
+            #[cfg(feature = "tui")]
+            Commands::Browse(args) => &args.repository,
+            _ => return None,
+        };
+        repository.path.as_deref()
+    }
+}
+
+fn run(cli: Cli) -> Result<(), HarvestError> {
+    init_color(cli.color);
+    init_logging(cli.verbose);
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    // config works on the files themselves, it needs neither their settings nor a remote
+    if let Commands::Config { action } = &cli.command {
+        let location = cli.location.first().map_or(".", String::as_str);
+        return config_command(location, action);
+    }
+    // Flags win over the repository's config file, which wins over the user's
+    let user_settings = config::load_user()?;
+    let mut locations = cli.location.clone();
+    if let Some(path) = cli.command.repository_path() {
+        if !locations.is_empty() {
+            return Err(
+                "the repository is given both as PATH and with --location, pass only one".into(),
+            );
+        }
+        locations.push(path.to_string());
+    }
+    if let Some(dir) = &cli.scan {
+        locations.extend(scan_repositories(dir)?);
+    }
+    if locations.is_empty() {
+        locations.push(user_settings.location.clone().unwrap_or(".".to_string()));
+    }
+    let mut sources = Vec::new();
+    for location in &locations {
+        let repo_settings = config::load_repo(&repo_root(location))?;
+        sources.push(Source {
+            location: location.clone(),
+            remote: cli
+                .remote
+                .clone()
+                .or(repo_settings.remote)
+                .or(user_settings.remote.clone())
+                .unwrap_or("origin".to_string()),
+            include_local: cli.include_local,
+            include_symbolic: cli.include_symbolic,
+            fetch: cli.fetch,
+            by: cli.by,
+            default_branch: cli.default_branch.clone(),
+            cache: cli.cache.clone(),
+        });
+    }
+    let settings = config::load_repo(&repo_root(&locations[0]))?.or(user_settings);
+    // Dropped when run returns, which waits for the pager to be quit
+    let _pager = match (&cli.command, cli.paginate) {
+        (Commands::List(_) | Commands::Stale(_), Paginate::Always) => pager::start(),
+        (Commands::List(_) | Commands::Stale(_), Paginate::Auto)
+            if std::io::stdout().is_terminal() =>
+        {
+            pager::start()
+        }
+        _ => None,
+    };
+    if let Some(url) = &cli.remote_url {
+        return match &cli.command {
+            Commands::Stats(args) => remote_stats(url, args),
+            Commands::List(args) => remote_list(url, args),
+            _ => Err("--remote-url only works with stats --by-prefix and list".into()),
+        };
+    }
+    if sources.len() > 1 {
+        return match &cli.command {
+            Commands::Stats(args) => stats(&sources, args),
+            _ => {
+                Err("only stats works across several repositories, pass a single --location".into())
+            }
+        };
+    }
+    let source = sources.remove(0);
     match &cli.command {
-        Some(Commands::Stats {}) => {
-            return stats(location.as_str());
+        Commands::Stats(args) => stats(std::slice::from_ref(&source), args),
+        Commands::Tags(args) => tags(&source, args),
+        Commands::Top { n, .. } => top(&source, *n),
+        Commands::Compare(args) => compare(&source, args),
+        Commands::Cleanup(args) => {
+            // A missing repository is reported before anything is worked out or printed
+            get_repo(source.location.clone())?;
+            // Branches with nothing of their own are safe to delete whoever wrote them
+            let filter = BranchFilter::new(&args.filter, &source.location, !args.only_zero_ahead)?;
+            let mut protect = args.protect.clone();
+            if !args.no_protect {
+                protect.extend(settings.protect);
+            }
+            let protected = protected_branches(&protect, args.no_protect)?;
+            if let Some(filter_email) = filter.email.as_ref().filter(|_| !args.json) {
+                inform!("filter_email: {}\n==========================", filter_email);
+            }
+            let bulk_threshold = settings.bulk_threshold.unwrap_or(BULK_THRESHOLD);
+            cleanup(&source, &filter, &protected, args, bulk_threshold)
+        }
+        Commands::List(args) => {
+            get_repo(source.location.clone())?;
+            let filter = BranchFilter::new(&args.filter, &source.location, true)?;
+            // Keep machine readable output free of the banner
+            if let Some(filter_email) = filter.email.as_ref().filter(|_| !args.csv && !args.json) {
+                inform!("filter_email: {}\n==========================", filter_email);
+            }
+            list(&source, &filter, args)
         }
-        Some(Commands::Cleanup { email }) => {
-            let filter_email = email.as_deref().unwrap_or(user_email.as_str());
-            println!("filter_email: {}\n==========================", filter_email);
-            return cleanup(location.as_str(), filter_email);
+        Commands::Stale(args) => stale(
+            &source,
+            args.days.or(settings.stale_days).unwrap_or(90),
+            args,
+        ),
+        Commands::PruneLocal(args) => prune_local(&source, args),
+        Commands::Orphans(args) => {
+            let protected = protected_branches(&settings.protect, false)?;
+            let mut naming = args.naming.clone();
+            naming.extend(settings.naming);
+            orphans(&source, &protected, &naming, args)
         }
-        Some(Commands::List { email }) => {
-            let filter_email = email.as_deref().unwrap_or(user_email.as_str());
-            println!("filter_email: {}\n==========================", filter_email);
-            return list(location.as_str(), filter_email);
+        Commands::Restore { branch, commit } => restore(&source, branch, commit.as_deref()),
+        #[cfg(feature = "tui")]
+        Commands::Browse(args) => {
+            get_repo(source.location.clone())?;
+            let filter = BranchFilter::new(&args.filter, &source.location, true)?;
+            let protected = protected_branches(&settings.protect, false)?;
+            browse(&source, &filter, &protected, args)
         }
-        None => {
-            println!(
-                "Please provide a subcommand, use help sub command to see the available options"
+        Commands::Rename(args) => {
+            let protected = protected_branches(&settings.protect, false)?;
+            rename(&source, &protected, args)
+        }
+        Commands::Offboard(args) => {
+            let protected = protected_branches(&settings.protect, false)?;
+            offboard(&source, &protected, args)
+        }
+        Commands::Whoami => whoami(&source.location),
+        Commands::Config { .. } => unreachable!("handled before loading the config files"),
+        Commands::Completions { shell } => {
+            clap_complete::generate(
+                *shell,
+                &mut Cli::command(),
+                "git-harvest",
+                &mut std::io::stdout(),
             );
             Ok(())
         }
     }
 }
+
+/// An error as --error-format json prints it
+#[derive(Serialize)]
+struct ErrorReport {
+    error: String,
+    kind: &'static str,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let error_format = cli.error_format;
+    if let Err(err) = run(cli) {
+        match error_format {
+            ErrorFormat::Text => eprintln!("error: {}", err),
+            ErrorFormat::Json => {
+                let report = ErrorReport {
+                    error: err.to_string(),
+                    kind: err.kind(),
+                };
+                let report = serde_json::to_string(&report).unwrap_or_default();
+                eprintln!("{}", report);
+            }
+        }
+        std::process::exit(err.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+
+    /// Commits an empty tree to the ref, with a fixed author
+    fn commit(repo: &Repository, refname: &str) -> Oid {
+        let signature = Signature::new("Alice", "alice@corp.com", &Time::new(1_700_000_000, 0))
+            .expect("signature");
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        repo.commit(Some(refname), &signature, &signature, "commit", &tree, &[])
+            .expect("commit")
+    }
+
+    #[test]
+    fn branch_ref_keeps_slashes_dots_and_unicode() {
+        for name in ["feature/JIRA-123/fix", "release/1.2.3", "ünï/çødé.v2"] {
+            assert_eq!(branch_ref(name), Ok(format!("refs/heads/{}", name)));
+        }
+    }
+
+    #[test]
+    fn branch_ref_rejects_names_git_refuses() {
+        for name in ["foo bar", "a:b", "x*"] {
+            assert!(branch_ref(name).is_err(), "{} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn refused_credentials_are_auth_failures() {
+        let context = |e: String| format!("failed to delete branch 'x': {}", e);
+        let refused = PushFailure {
+            message: "authentication failed".to_string(),
+            transient: false,
+            auth: true,
+        };
+        let err = refused.into_error(context, HarvestError::DeleteFailed);
+        assert_eq!(err.kind(), "AuthFailed");
+        assert_eq!(
+            err.to_string(),
+            "failed to delete branch 'x': authentication failed"
+        );
+        let rejected = PushFailure::permanent("protected branch hook declined".to_string());
+        let err = rejected.into_error(context, HarvestError::DeleteFailed);
+        assert_eq!(err.kind(), "DeleteFailed");
+    }
+
+    #[test]
+    fn a_missing_repository_is_reported_before_the_email() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing").display().to_string();
+        for args in [
+            &["list"][..],
+            &["list", "--email", "x"],
+            &["cleanup", "--dry-run"],
+        ] {
+            let cli =
+                Cli::try_parse_from(["git-harvest", "-l", &missing].iter().chain(args)).unwrap();
+            assert_eq!(run(cli).unwrap_err().kind(), "NotARepo", "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn remote_list_refuses_what_needs_commits() {
+        let url = "https://example.invalid/repo.git";
+        for flag in [
+            "--committer-email=a",
+            "--sort=date",
+            "--show-committer",
+            "--show-churn",
+        ] {
+            let cli =
+                Cli::try_parse_from(["git-harvest", "--remote-url", url, "list", flag]).unwrap();
+            let Commands::List(args) = &cli.command else {
+                unreachable!("parsed a list");
+            };
+            let err = remote_list(url, args).unwrap_err();
+            assert_eq!(err.kind(), "InvalidFilter", "{}", flag);
+            assert!(err.to_string().contains(flag.split('=').next().unwrap()));
+        }
+    }
+
+    #[test]
+    fn groups_keep_their_json_names() {
+        let group = |key: &str, commits_ahead| GroupStats {
+            key: key.to_string(),
+            count: 2,
+            commits_ahead,
+            commits: None,
+            unique_tips: None,
+        };
+        let groups = Groups {
+            grouping: Grouping::Prefix,
+            list: vec![group("feature/", Some(3)), group("<no-prefix>", None)],
+        };
+        assert_eq!(
+            serde_json::to_value(&groups).unwrap(),
+            serde_json::json!({"per_prefix": [
+                {"prefix": "feature/", "count": 2, "commits_ahead": 3},
+                {"prefix": "<no-prefix>", "count": 2},
+            ]})
+        );
+        let groups = Groups {
+            grouping: Grouping::User,
+            list: vec![group("alice@corp.com", None)],
+        };
+        assert_eq!(
+            serde_json::to_string(&groups).unwrap(),
+            r#"{"per_user":[{"email":"alice@corp.com","count":2}]}"#
+        );
+    }
+
+    #[test]
+    fn stats_group_emails_whatever_their_case() {
+        let branch = |name: &str, email: &str| BranchInfo {
+            name: name.to_string(),
+            kind: BranchType::Remote,
+            author_name: "Alice".to_string(),
+            author_email: email.to_string(),
+            committer_name: "Alice".to_string(),
+            committer_email: email.to_string(),
+            time: Time::new(1_700_000_000, 0),
+            tip: Oid::zero(),
+        };
+        let branches = [
+            branch("a", "Alice@Corp.com"),
+            branch("b", "alice@corp.com"),
+            branch("c", "bob@corp.com"),
+        ];
+        let cli = Cli::try_parse_from(["git-harvest", "stats"]).unwrap();
+        let Commands::Stats(args) = &cli.command else {
+            unreachable!("parsed a stats");
+        };
+        let report = stats_report(&branches, None, None, args);
+        let groups: Vec<(&str, usize)> = report
+            .groups
+            .list
+            .iter()
+            .map(|group| (group.key.as_str(), group.count))
+            .collect();
+        assert_eq!(groups, [("alice@corp.com", 2), ("bob@corp.com", 1)]);
+        assert_eq!(report.distinct_authors, Some(2));
+        assert_eq!(
+            count_per_user(&branches)[0],
+            ("alice@corp.com".to_string(), 2)
+        );
+    }
+
+    fn names(branches: &[BranchInfo]) -> Vec<&str> {
+        let mut names: Vec<&str> = branches.iter().map(|branch| branch.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn refs_to_trees_and_blobs_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        repo.remote("origin", "https://example.invalid/repo.git")
+            .unwrap();
+        commit(&repo, "refs/remotes/origin/main");
+        let tree = repo.treebuilder(None).unwrap().write().unwrap();
+        let blob = repo.blob(b"not a commit").unwrap();
+        repo.reference("refs/remotes/origin/tree", tree, false, "test")
+            .unwrap();
+        repo.reference("refs/remotes/origin/blob", blob, false, "test")
+            .unwrap();
+
+        let branches =
+            get_remote_branches(&repo, "origin", false, false, Identity::Author, None).unwrap();
+        assert_eq!(names(&branches), ["main"]);
+    }
+
+    fn source(location: &Path) -> Source {
+        Source {
+            location: location.display().to_string(),
+            remote: "origin".to_string(),
+            include_local: false,
+            include_symbolic: false,
+            fetch: false,
+            by: Identity::Author,
+            default_branch: None,
+            cache: None,
+        }
+    }
+
+    #[test]
+    fn bare_repository_heads_are_its_branches() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        commit(&repo, "refs/heads/main");
+        commit(&repo, "refs/heads/feature/x");
+
+        let branches = source(dir.path()).branches(&repo).unwrap();
+        assert_eq!(names(&branches), ["feature/x", "main"]);
+        assert!(branches
+            .iter()
+            .all(|branch| branch.kind == BranchType::Local));
+    }
+
+    #[test]
+    fn cleanup_refuses_a_bare_mirror_with_a_remote() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        repo.remote("origin", "https://example.invalid/repo.git")
+            .unwrap();
+        commit(&repo, "refs/heads/main");
+        commit(&repo, "refs/heads/feature/x");
+
+        let cli =
+            Cli::try_parse_from(["git-harvest", "cleanup", "--dry-run", "--email", "x"]).unwrap();
+        let Commands::Cleanup(args) = &cli.command else {
+            unreachable!("parsed a cleanup");
+        };
+        let source = source(dir.path());
+        let filter = BranchFilter::new(&args.filter, &source.location, false).unwrap();
+        let protected = protected_branches(&[], false).unwrap();
+        let err = cleanup(&source, &filter, &protected, args, BULK_THRESHOLD).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("is a bare copy of remote 'origin'"));
+        // Nothing was deleted
+        assert!(repo.find_reference("refs/heads/feature/x").is_ok());
+    }
+}
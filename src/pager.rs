@@ -0,0 +1,87 @@
+//! Sends the output through a pager, the way git does for long output.
+//!
+//! The pager is `$PAGER`, or `less`. Like git, `LESS=FRX` is set when `LESS` is not, so
+//! output fitting on one screen is printed as is and colors pass through.
+
+use log::debug;
+use std::io::Write;
+
+/// A running pager fed by stdout, waited for when dropped
+pub struct Pager {
+    #[cfg(unix)]
+    child: std::process::Child,
+    #[cfg(unix)]
+    saved_stdout: std::os::fd::RawFd,
+}
+
+/// Starts the pager and points stdout at it, None when there is no pager to run
+#[cfg(unix)]
+pub fn start() -> Option<Pager> {
+    use std::os::fd::AsRawFd;
+    use std::process::{Command, Stdio};
+
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    if pager.trim().is_empty() || pager == "cat" {
+        return None;
+    }
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&pager).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("unable to start pager '{}': {}", pager, e);
+            return None;
+        }
+    };
+    let pipe = child.stdin.as_ref()?.as_raw_fd();
+    // SAFETY: plain descriptor juggling on fds this process owns, stdout is flushed first
+    let saved_stdout = unsafe {
+        let _ = std::io::stdout().flush();
+        let saved = libc::dup(libc::STDOUT_FILENO);
+        if saved < 0 || libc::dup2(pipe, libc::STDOUT_FILENO) < 0 {
+            debug!("unable to redirect stdout to pager '{}'", pager);
+            return None;
+        }
+        saved
+    };
+    // Quitting the pager early ends this process quietly, as it does git, instead of
+    // failing on the next write
+    // SAFETY: restores the default disposition, no handler is involved
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    debug!("paging output through '{}'", pager);
+    Some(Pager {
+        child,
+        saved_stdout,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn start() -> Option<Pager> {
+    None
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        #[cfg(unix)]
+        {
+            // Putting the terminal back on stdout and closing our end of the pipe lets the
+            // pager see the end of the output
+            // SAFETY: saved_stdout was duplicated from stdout in start and is only used here
+            unsafe {
+                libc::dup2(self.saved_stdout, libc::STDOUT_FILENO);
+                libc::close(self.saved_stdout);
+            }
+            drop(self.child.stdin.take());
+            let _ = self.child.wait();
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! `--format` templates such as `'{age}d {name} <{email}>'`.
+
+/// A value a template can refer to with `{placeholder}`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Name,
+    Email,
+    Author,
+    Date,
+    Age,
+    Created,
+    Churn,
+    Ahead,
+    Behind,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 9] = [
+        ("name", Field::Name),
+        ("email", Field::Email),
+        ("author", Field::Author),
+        ("date", Field::Date),
+        ("age", Field::Age),
+        ("created", Field::Created),
+        ("churn", Field::Churn),
+        ("ahead", Field::Ahead),
+        ("behind", Field::Behind),
+    ];
+
+    fn from_name(name: &str) -> Option<Field> {
+        Field::ALL
+            .iter()
+            .find(|(field_name, _)| *field_name == name)
+            .map(|(_, field)| *field)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses a template, `{{` and `}}` stand for literal braces.
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder '{{{}'", name)),
+                        }
+                    }
+                    let field = Field::from_name(&name).ok_or_else(|| {
+                        let known: Vec<String> = Field::ALL
+                            .iter()
+                            .map(|(name, _)| format!("{{{}}}", name))
+                            .collect();
+                        format!(
+                            "unknown placeholder '{{{}}}', expected one of {}",
+                            name,
+                            known.join(", ")
+                        )
+                    })?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(field));
+                }
+                '}' => return Err("unmatched '}', use '}}' for a literal brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Template { segments })
+    }
+
+    /// Whether the template refers to the field, for the ones that are costly to compute.
+    pub fn uses(&self, field: Field) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Field(f) if *f == field))
+    }
+
+    pub fn render(&self, value: impl Fn(Field) -> String) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Field(field) => value(*field),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> String {
+        Template::parse(template)
+            .unwrap()
+            .render(|field| match field {
+                Field::Name => "feature/x".to_string(),
+                Field::Email => "alice@corp.com".to_string(),
+                Field::Age => "12".to_string(),
+                other => format!("<{:?}>", other),
+            })
+    }
+
+    #[test]
+    fn placeholders_are_replaced_in_place() {
+        assert_eq!(
+            render("{age}d {name} <{email}>"),
+            "12d feature/x <alice@corp.com>"
+        );
+        assert_eq!(render("plain text"), "plain text");
+        assert_eq!(render(""), "");
+    }
+
+    #[test]
+    fn adjacent_placeholders_are_not_merged() {
+        assert_eq!(render("{name}{email}"), "feature/xalice@corp.com");
+        assert_eq!(render("{age}{age}"), "1212");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{name}}"), "{name}");
+        assert_eq!(render("{{{name}}}"), "{feature/x}");
+        assert_eq!(render("}}{{"), "}{");
+    }
+
+    #[test]
+    fn unknown_and_unbalanced_placeholders_are_rejected() {
+        let err = Template::parse("{nmae}").unwrap_err();
+        assert!(err.starts_with("unknown placeholder '{nmae}'"), "{}", err);
+        assert!(err.contains("{name}"), "{}", err);
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("{Name}").is_err());
+        assert_eq!(
+            Template::parse("{name").unwrap_err(),
+            "unclosed placeholder '{name'"
+        );
+        assert!(Template::parse("name}").is_err());
+    }
+
+    #[test]
+    fn uses_reports_only_the_fields_present() {
+        let template = Template::parse("{name} {{churn}} {ahead}").unwrap();
+        assert!(template.uses(Field::Name));
+        assert!(template.uses(Field::Ahead));
+        assert!(!template.uses(Field::Churn));
+        assert!(!template.uses(Field::Behind));
+    }
+}